@@ -1,12 +1,64 @@
+use std::fmt::{Debug, Display};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::num::ParseIntError;
 use std::path::Path;
+use std::str::FromStr;
+
+// the integer operations the interval code needs, implemented for all the
+// primitive integer types
+trait Id: Copy + Ord + Debug + Display + Default + FromStr<Err = ParseIntError> {
+    // the value one less/more than self. Only called where the range
+    // comparisons guarantee it can't under/overflow
+    fn prev(self) -> Self;
+    fn next(self) -> Self;
+    // `self - start` for `start <= self`, which always fits in a u128 even
+    // for i128
+    fn distance_from(self, start: Self) -> u128;
+}
+
+macro_rules! impl_id {
+    ($($t:ty),*) => {
+        $(
+            impl Id for $t {
+                fn prev(self) -> Self {
+                    self - 1
+                }
+
+                fn next(self) -> Self {
+                    self + 1
+                }
+
+                fn distance_from(self, start: Self) -> u128 {
+                    self.abs_diff(start) as u128
+                }
+            }
+        )*
+    };
+}
+
+impl_id!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize
+);
+
+// parses a range like `3-5` or, for signed types, `-5--1`. The separator is
+// the first `-` after the first character since the start may be negative
+fn parse_range<T: Id>(s: &str) -> (T, T) {
+    let s = s.trim();
+    let split = s
+        .char_indices()
+        .skip(1)
+        .find(|&(_, c)| c == '-')
+        .map(|(i, _)| i)
+        .unwrap();
+    (s[..split].parse().unwrap(), s[split + 1..].parse().unwrap())
+}
 
 #[derive(Default, Debug, Clone)]
-struct IdRanges(Vec<(u64, u64)>);
+struct IdRanges<T: Id = u64>(Vec<(T, T)>);
 
-impl IdRanges {
-    fn add(self, (start, finish): (u64, u64)) -> Self {
+impl<T: Id> IdRanges<T> {
+    fn add(self, (start, finish): (T, T)) -> Self {
         let mut output = Self(Vec::with_capacity(self.0.len() + 1));
         let mut had_merge = false;
         for (x1, x2) in self.0.into_iter() {
@@ -24,56 +76,190 @@ impl IdRanges {
         output
     }
 
-    fn contains(&self, target: &u64) -> bool {
-        self.0
-            .iter()
-            .any(|(start, finish)| start <= target && finish >= target)
+    // removes a range, splitting any existing range that has a hole punched
+    // in its middle
+    fn remove(self, (start, finish): (T, T)) -> Self {
+        let mut output = Self(Vec::with_capacity(self.0.len() + 1));
+        for (x1, x2) in self.0.into_iter() {
+            if start > x2 || finish < x1 {
+                output.0.push((x1, x2));
+                continue;
+            }
+            // the comparisons guarantee these can't under/overflow
+            if x1 < start {
+                output.0.push((x1, start.prev()));
+            }
+            if x2 > finish {
+                output.0.push((finish.next(), x2));
+            }
+        }
+
+        output
     }
 
-    fn total_ids(&self) -> u64 {
+    fn contains(&self, target: &T) -> bool {
+        self.find(target).is_some()
+    }
+
+    // returns the range that contains the target, if any
+    fn find(&self, target: &T) -> Option<(T, T)> {
         self.0
             .iter()
-            .map(|(start, finish)| finish + 1 - start)
-            .sum()
+            .find(|(start, finish)| start <= target && finish >= target)
+            .copied()
+    }
+
+    // the number of ids in the full `0-u64::MAX` domain is one more than fits
+    // in a u64, so the count is done in u128. For 128 bit types even that can
+    // overflow, in which case this returns None
+    fn checked_total_ids(&self) -> Option<u128> {
+        self.0.iter().try_fold(0u128, |acc, (start, finish)| {
+            finish
+                .distance_from(*start)
+                .checked_add(1)?
+                .checked_add(acc)
+        })
+    }
+
+    fn total_ids(&self) -> u128 {
+        self.checked_total_ids()
+            .expect("number of ids does not fit in a u128")
     }
 }
 
+// why an available ingredient is fresh: the merged range it falls in and the
+// (1-indexed) input lines of the fresh ranges that make up that merged range
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct FreshMatch {
+    range: (u64, u64),
+    lines: Vec<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct IngredientReport {
+    id: u64,
+    fresh: Option<FreshMatch>,
+}
+
 #[derive(Clone, Debug)]
 struct IngredientPantry {
     fresh_ingredients: IdRanges,
+    // the fresh ranges as written in the input along with their line numbers
+    fresh_sources: Vec<(usize, (u64, u64))>,
     available: Vec<u64>,
 }
 
 impl IngredientPantry {
     fn from_file(fname: impl AsRef<Path>) -> Self {
         let f = File::open(fname.as_ref()).unwrap();
-        let reader = BufReader::new(f);
+        Self::from_reader(BufReader::new(f))
+    }
 
-        let mut is_preamble = true;
+    fn from_reader(reader: impl BufRead) -> Self {
+        // the input is split into sections by blank lines: fresh ranges,
+        // available ids, and then optionally spoiled ranges. A run of blank
+        // lines only counts once
+        let mut section = 0;
+        let mut after_blank = true;
         let mut fresh_ingredients = IdRanges::default();
+        let mut fresh_sources = vec![];
         let mut available = vec![];
-        for line in reader.lines() {
+        let mut spoiled = vec![];
+        for (i, line) in reader.lines().enumerate() {
             let line = line.unwrap();
             if line.trim().is_empty() {
-                is_preamble = false;
+                if !after_blank {
+                    section += 1;
+                }
+                after_blank = true;
                 continue;
             }
-            if is_preamble {
-                let (start, finish) = line.split_once('-').unwrap();
-                let start: u64 = start.parse().unwrap();
-                let finish: u64 = finish.parse().unwrap();
-                fresh_ingredients = fresh_ingredients.add((start, finish));
-            } else {
-                let id = line.trim().parse().unwrap();
-                available.push(id)
+            after_blank = false;
+            match section {
+                0 => {
+                    let range = parse_range(&line);
+                    fresh_sources.push((i + 1, range));
+                    fresh_ingredients = fresh_ingredients.add(range);
+                }
+                1 => available.push(line.trim().parse().unwrap()),
+                2 => spoiled.push(parse_range(&line)),
+                _ => panic!("unexpected input section"),
             }
         }
+        // spoiled ranges are only removed once all the fresh ones are known
+        for range in spoiled {
+            fresh_ingredients = fresh_ingredients.remove(range);
+        }
         Self {
             fresh_ingredients,
+            fresh_sources,
             available,
         }
     }
 
+    fn report(&self) -> Vec<IngredientReport> {
+        self.available
+            .iter()
+            .map(|&id| {
+                let fresh = self.fresh_ingredients.find(&id).map(|(start, finish)| {
+                    // any input range overlapping the merged range contributed to it
+                    let lines = self
+                        .fresh_sources
+                        .iter()
+                        .filter(|(_, (x1, x2))| *x1 <= finish && *x2 >= start)
+                        .map(|(line, _)| *line)
+                        .collect();
+                    FreshMatch {
+                        range: (start, finish),
+                        lines,
+                    }
+                });
+                IngredientReport { id, fresh }
+            })
+            .collect()
+    }
+
+    fn report_table(&self) -> String {
+        let mut table = format!("{:<20} {:<5} {:<41} {}\n", "id", "fresh", "range", "lines");
+        for IngredientReport { id, fresh } in self.report() {
+            let (is_fresh, range, lines) = match fresh {
+                Some(FreshMatch {
+                    range: (start, finish),
+                    lines,
+                }) => {
+                    let lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+                    ("yes", format!("{start}-{finish}"), lines.join(","))
+                }
+                None => ("no", "-".to_string(), "-".to_string()),
+            };
+            table.push_str(&format!("{id:<20} {is_fresh:<5} {range:<41} {lines}\n"));
+        }
+        table
+    }
+
+    // everything in the report is a number or a bool so it's simple enough to
+    // write the json by hand
+    fn report_json(&self) -> String {
+        let entries: Vec<String> = self
+            .report()
+            .into_iter()
+            .map(|IngredientReport { id, fresh }| match fresh {
+                Some(FreshMatch {
+                    range: (start, finish),
+                    lines,
+                }) => {
+                    let lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+                    format!(
+                        "{{\"id\":{id},\"fresh\":true,\"range\":[{start},{finish}],\"lines\":[{}]}}",
+                        lines.join(",")
+                    )
+                }
+                None => format!("{{\"id\":{id},\"fresh\":false,\"range\":null,\"lines\":[]}}"),
+            })
+            .collect();
+        format!("[{}]", entries.join(","))
+    }
+
     fn num_fresh_available(&self) -> usize {
         self.available
             .iter()
@@ -81,7 +267,7 @@ impl IngredientPantry {
             .count()
     }
 
-    fn num_fresh(&self) -> u64 {
+    fn num_fresh(&self) -> u128 {
         self.fresh_ingredients.total_ids()
     }
 }
@@ -90,6 +276,13 @@ fn main() {
     let pantry = IngredientPantry::from_file("input.txt");
     println!("Part A: `{}`", pantry.num_fresh_available());
     println!("Part B: `{}`", pantry.num_fresh());
+
+    match std::env::args().nth(1).as_deref() {
+        Some("--table") => print!("{}", pantry.report_table()),
+        Some("--json") => println!("{}", pantry.report_json()),
+        Some(other) => panic!("unknown option `{other}`, expected `--table` or `--json`"),
+        None => {}
+    }
 }
 
 #[cfg(test)]
@@ -107,4 +300,162 @@ mod tests {
         let pantry = IngredientPantry::from_file("test.txt");
         assert_eq!(14, pantry.num_fresh())
     }
+
+    #[test]
+    fn test_extra_blank_lines() {
+        let input = "\n3-5\n10-14\n16-20\n12-18\n\n\n\n1\n5\n8\n11\n17\n32\n\n";
+        let pantry = IngredientPantry::from_reader(input.as_bytes());
+        assert_eq!(3, pantry.num_fresh_available());
+        assert_eq!(14, pantry.num_fresh());
+    }
+
+    #[test]
+    fn test_spoiled() {
+        let pantry = IngredientPantry::from_file("test_spoiled.txt");
+        assert_eq!(2, pantry.num_fresh_available());
+        assert_eq!(11, pantry.num_fresh())
+    }
+
+    #[test]
+    fn test_report() {
+        let pantry = IngredientPantry::from_file("test.txt");
+        let report = pantry.report();
+        assert_eq!(6, report.len());
+        assert_eq!(IngredientReport { id: 1, fresh: None }, report[0]);
+        assert_eq!(
+            Some(FreshMatch {
+                range: (3, 5),
+                lines: vec![1]
+            }),
+            report[1].fresh
+        );
+        assert_eq!(
+            Some(FreshMatch {
+                range: (10, 20),
+                lines: vec![2, 3, 4]
+            }),
+            report[4].fresh
+        );
+        assert_eq!(
+            pantry.num_fresh_available(),
+            report.iter().filter(|r| r.fresh.is_some()).count()
+        );
+    }
+
+    #[test]
+    fn test_report_spoiled() {
+        let pantry = IngredientPantry::from_file("test_spoiled.txt");
+        let report = pantry.report();
+        assert_eq!(
+            Some(FreshMatch {
+                range: (5, 5),
+                lines: vec![1]
+            }),
+            report[1].fresh
+        );
+        assert_eq!(None, report[3].fresh);
+        assert_eq!(
+            Some(FreshMatch {
+                range: (13, 20),
+                lines: vec![2, 3, 4]
+            }),
+            report[4].fresh
+        );
+    }
+
+    #[test]
+    fn test_report_json() {
+        let pantry = IngredientPantry::from_file("test.txt");
+        let json = pantry.report_json();
+        assert!(json.starts_with(r#"[{"id":1,"fresh":false,"range":null,"lines":[]},"#));
+        assert!(json.contains(r#"{"id":17,"fresh":true,"range":[10,20],"lines":[2,3,4]}"#));
+    }
+
+    #[test]
+    fn test_remove() {
+        let ranges = IdRanges::default().add((10, 20));
+        assert_eq!(vec![(10, 12), (16, 20)], ranges.clone().remove((13, 15)).0);
+        assert_eq!(vec![(16, 20)], ranges.clone().remove((0, 15)).0);
+        assert_eq!(vec![(10, 12)], ranges.clone().remove((13, 30)).0);
+        assert!(ranges.clone().remove((10, 20)).0.is_empty());
+        assert_eq!(vec![(10, 20)], ranges.remove((21, 30)).0);
+    }
+
+    #[test]
+    fn test_remove_domain_extremes() {
+        let ranges = IdRanges::default().add((0, u64::MAX));
+        let ranges = ranges.remove((0, 0)).remove((u64::MAX, u64::MAX));
+        assert_eq!(vec![(1, u64::MAX - 1)], ranges.0);
+        assert_eq!(u128::from(u64::MAX) - 1, ranges.total_ids());
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!((3u64, 5), parse_range("3-5"));
+        assert_eq!((-5i64, -1), parse_range("-5--1"));
+        assert_eq!((-5i32, 3), parse_range(" -5-3 "));
+        assert_eq!((i8::MIN, i8::MAX), parse_range("-128-127"));
+    }
+
+    #[test]
+    fn test_signed() {
+        let ranges = IdRanges::default()
+            .add(parse_range::<i64>("-5--1"))
+            .add((-3, 2))
+            .add((10, 12));
+        assert_eq!(vec![(-5, 2), (10, 12)], ranges.0);
+        assert!(ranges.contains(&-5));
+        assert!(!ranges.contains(&-6));
+        assert_eq!(11, ranges.total_ids());
+
+        let ranges = ranges.remove((-1, -1));
+        assert_eq!(vec![(-5, -2), (0, 2), (10, 12)], ranges.0);
+        assert_eq!(10, ranges.total_ids());
+    }
+
+    #[test]
+    fn test_other_widths() {
+        let ranges = IdRanges::default().add((i8::MIN, i8::MAX));
+        assert_eq!(256, ranges.total_ids());
+        let ranges = ranges.remove((0, 0));
+        assert_eq!(vec![(i8::MIN, -1), (1, i8::MAX)], ranges.0);
+
+        let ranges = IdRanges::default().add((0u32, 10)).add((5, u32::MAX));
+        assert_eq!(u128::from(u32::MAX) + 1, ranges.total_ids());
+
+        let ranges = IdRanges::default().add((i128::MIN, i128::MAX));
+        assert_eq!(None, ranges.checked_total_ids());
+        let ranges = ranges.remove((i128::MAX, i128::MAX));
+        assert_eq!(Some(u128::MAX), ranges.checked_total_ids());
+
+        let ranges = IdRanges::default().add((0u128, u128::MAX));
+        assert_eq!(None, ranges.checked_total_ids());
+    }
+
+    #[test]
+    fn test_full_domain() {
+        let ranges = IdRanges::default().add((0, u64::MAX));
+        assert!(ranges.contains(&0));
+        assert!(ranges.contains(&u64::MAX));
+        assert_eq!(u128::from(u64::MAX) + 1, ranges.total_ids());
+
+        // merging anything into the full domain should not change it
+        let ranges = ranges.add((5, 10)).add((u64::MAX, u64::MAX));
+        assert_eq!(vec![(0, u64::MAX)], ranges.0);
+        assert_eq!(u128::from(u64::MAX) + 1, ranges.total_ids());
+    }
+
+    #[test]
+    fn test_domain_extremes() {
+        let ranges = IdRanges::default()
+            .add((u64::MAX, u64::MAX))
+            .add((0, 0))
+            .add((u64::MAX - 10, u64::MAX - 1));
+        assert!(ranges.contains(&0));
+        assert!(!ranges.contains(&1));
+        assert!(!ranges.contains(&(u64::MAX - 11)));
+        assert!(ranges.contains(&(u64::MAX - 10)));
+        assert!(ranges.contains(&u64::MAX));
+        assert_eq!(12, ranges.total_ids());
+    }
 }
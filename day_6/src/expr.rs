@@ -0,0 +1,252 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Operation, SolveError, Value};
+
+// an infix expression like `a*b+c` that can sit at the bottom of a column in
+// place of a single operator. The letters refer to the column's numbers in
+// order, so `a` is the first number, `b` the second and so on.
+//
+// Precedence is the usual one: `^` binds tightest and is right associative,
+// then `*` and `/`, then `+` and `-`, which are all left associative
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Var(usize),
+    // kept as a string so it can be parsed into whatever `Value` the
+    // expression is evaluated in
+    Literal(String),
+    Binary {
+        op: Operation,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+}
+
+impl Expr {
+    pub fn eval<T: Value>(&self, args: &[T]) -> Result<T, SolveError> {
+        match self {
+            Expr::Var(i) => args.get(*i).cloned().ok_or(SolveError::MissingArgument),
+            Expr::Literal(s) => T::parse(s),
+            Expr::Binary { op, lhs, rhs } => T::apply(*op, lhs.eval(args)?, rhs.eval(args)?),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseExprError {
+    // byte offset into the expression where parsing failed
+    pos: usize,
+}
+
+impl fmt::Display for ParseExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid expression at position {}", self.pos)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Var(usize),
+    Literal(String),
+    Op(Operation),
+    Open,
+    Close,
+}
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, ParseExprError> {
+    let bytes = s.as_bytes();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let token = match bytes[i] {
+            b' ' => {
+                i += 1;
+                continue;
+            }
+            b'0'..=b'9' => {
+                let len = bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+                tokens.push((i, Token::Literal(s[i..i + len].to_string())));
+                i += len;
+                continue;
+            }
+            // variables are a single letter, so `ab` is an error rather than
+            // an implicit multiplication
+            b @ b'a'..=b'z' if !bytes.get(i + 1).is_some_and(u8::is_ascii_alphabetic) => {
+                Token::Var(usize::from(b - b'a'))
+            }
+            b'+' => Token::Op(Operation::Add),
+            b'-' => Token::Op(Operation::Sub),
+            b'*' => Token::Op(Operation::Mult),
+            b'/' => Token::Op(Operation::Div),
+            b'^' => Token::Op(Operation::Pow),
+            b'(' => Token::Open,
+            b')' => Token::Close,
+            _ => return Err(ParseExprError { pos: i }),
+        };
+        tokens.push((i, token));
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+// recursive descent parser, one method per precedence level
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    // the length of the input, reported as the error position if it ends
+    // early
+    len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn error(&self) -> ParseExprError {
+        let pos = self.tokens.get(self.pos).map_or(self.len, |(i, _)| *i);
+        ParseExprError { pos }
+    }
+
+    // parses a chain of left associative operators from `ops`, with operands
+    // parsed by `next`
+    fn left_assoc(
+        &mut self,
+        ops: &[Operation],
+        next: fn(&mut Self) -> Result<Expr, ParseExprError>,
+    ) -> Result<Expr, ParseExprError> {
+        let mut lhs = next(self)?;
+        while let Some(&Token::Op(op)) = self.peek() {
+            if !ops.contains(&op) {
+                break;
+            }
+            self.pos += 1;
+            let rhs = next(self)?;
+            lhs = Expr::Binary {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn sum(&mut self) -> Result<Expr, ParseExprError> {
+        self.left_assoc(&[Operation::Add, Operation::Sub], Self::product)
+    }
+
+    fn product(&mut self) -> Result<Expr, ParseExprError> {
+        self.left_assoc(&[Operation::Mult, Operation::Div], Self::power)
+    }
+
+    fn power(&mut self) -> Result<Expr, ParseExprError> {
+        let base = self.atom()?;
+        if let Some(Token::Op(Operation::Pow)) = self.peek() {
+            self.pos += 1;
+            let exp = self.power()?;
+            return Ok(Expr::Binary {
+                op: Operation::Pow,
+                lhs: Box::new(base),
+                rhs: Box::new(exp),
+            });
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Expr, ParseExprError> {
+        let expr = match self.peek() {
+            Some(Token::Var(i)) => Expr::Var(*i),
+            Some(Token::Literal(s)) => Expr::Literal(s.clone()),
+            Some(Token::Open) => {
+                self.pos += 1;
+                let inner = self.sum()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(self.error());
+                }
+                inner
+            }
+            _ => return Err(self.error()),
+        };
+        self.pos += 1;
+        Ok(expr)
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ParseExprError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+            len: s.len(),
+        };
+        let expr = parser.sum()?;
+        if parser.pos != parser.tokens.len() {
+            return Err(parser.error());
+        }
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(s: &str, args: &[u64]) -> Result<u64, SolveError> {
+        s.parse::<Expr>().unwrap().eval(args)
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(Ok(2 * 3 + 4), eval("a*b+c", &[2, 3, 4]));
+        assert_eq!(Ok(2 + 3 * 4), eval("a+b*c", &[2, 3, 4]));
+        assert_eq!(Ok(10 - 4 / 2), eval("a-b/c", &[10, 4, 2]));
+        assert_eq!(Ok(3 * 2u64.pow(3)), eval("a*b^c", &[3, 2, 3]));
+    }
+
+    #[test]
+    fn test_associativity() {
+        assert_eq!(Ok(10 - 4 - 3), eval("a-b-c", &[10, 4, 3]));
+        assert_eq!(Ok(64 / 4 / 2), eval("a/b/c", &[64, 4, 2]));
+        // ^ is right associative, 2^(3^2) rather than (2^3)^2
+        assert_eq!(Ok(512), eval("a^b^c", &[2, 3, 2]));
+    }
+
+    #[test]
+    fn test_parentheses() {
+        assert_eq!(Ok((2 + 3) * 4), eval("(a+b)*c", &[2, 3, 4]));
+        assert_eq!(Ok(10 - (4 - 3)), eval("a-(b-c)", &[10, 4, 3]));
+        assert_eq!(Ok(7), eval("((a))", &[7]));
+    }
+
+    #[test]
+    fn test_literals_and_spaces() {
+        assert_eq!(Ok(2 * 5 + 10), eval(" 2 * a + 10 ", &[5]));
+        assert_eq!(Ok(25), eval("a*a", &[5]));
+    }
+
+    #[test]
+    fn test_literal_overflow() {
+        assert_eq!(
+            Err(SolveError::Overflow),
+            eval("a*100000000000000000000", &[1])
+        );
+    }
+
+    #[test]
+    fn test_missing_argument() {
+        assert_eq!(Err(SolveError::MissingArgument), eval("a+b+c", &[1, 2]));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Err(ParseExprError { pos: 0 }), "".parse::<Expr>());
+        assert_eq!(Err(ParseExprError { pos: 2 }), "a+".parse::<Expr>());
+        assert_eq!(Err(ParseExprError { pos: 4 }), "(a+b".parse::<Expr>());
+        assert_eq!(Err(ParseExprError { pos: 3 }), "a+b)".parse::<Expr>());
+        assert_eq!(Err(ParseExprError { pos: 2 }), "a b".parse::<Expr>());
+        assert_eq!(Err(ParseExprError { pos: 0 }), "min".parse::<Expr>());
+        assert_eq!(Err(ParseExprError { pos: 1 }), "a%b".parse::<Expr>());
+    }
+}
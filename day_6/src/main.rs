@@ -1,150 +1,537 @@
+mod expr;
+
+use std::fmt;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, Read};
+use std::num::IntErrorKind;
 use std::path::Path;
 
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
 use strum_macros::EnumString;
 
-#[derive(Debug, Clone, Copy, EnumString)]
+use expr::Expr;
+
+// every operation folds its arguments left to right, starting from the first
+// argument, so `-` on `[a, b, c]` is `(a - b) - c` and `^` is `(a ^ b) ^ c`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString)]
 enum Operation {
     #[strum(serialize = "+")]
     Add,
     #[strum(serialize = "*")]
     Mult,
+    #[strum(serialize = "-")]
+    Sub,
+    #[strum(serialize = "/")]
+    Div,
+    #[strum(serialize = "^")]
+    Pow,
+    #[strum(serialize = "min")]
+    Min,
+    #[strum(serialize = "max")]
+    Max,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SolveError {
+    DivisionByZero,
+    // the result doesn't fit in the number type, including going below zero
+    // for unsigned types
+    Overflow,
+    NegativeExponent,
+    // a power whose result would be too big to work out
+    ExponentTooLarge,
+    // an expression refers to a letter past the number of arguments
+    MissingArgument,
+    // an operator with no numbers above it to fold
+    NoArguments,
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolveError::DivisionByZero => write!(f, "division by zero"),
+            SolveError::Overflow => write!(f, "overflow"),
+            SolveError::NegativeExponent => write!(f, "negative exponent"),
+            SolveError::ExponentTooLarge => write!(f, "exponent too large"),
+            SolveError::MissingArgument => write!(f, "missing argument"),
+            SolveError::NoArguments => write!(f, "no arguments"),
+        }
+    }
+}
+
+// a `SolveError` along with the index of the problem in the worksheet that
+// caused it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ProblemError {
+    problem: usize,
+    error: SolveError,
+}
+
+impl fmt::Display for ProblemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "problem {}: {}", self.problem, self.error)
+    }
+}
+
+// the number types a worksheet can be evaluated in. `u64` uses checked
+// arithmetic and `BigInt` is exact for any worksheet
+trait Value: Sized + Clone {
+    // a number too big for the type is an overflow, anything that isn't a
+    // number at all panics
+    fn parse(s: &str) -> Result<Self, SolveError>;
+    fn apply(op: Operation, acc: Self, x: Self) -> Result<Self, SolveError>;
+    fn checked_add(self, other: Self) -> Option<Self>;
+}
+
+impl Value for u64 {
+    fn parse(s: &str) -> Result<Self, SolveError> {
+        match s.trim().parse() {
+            Ok(x) => Ok(x),
+            Err(e) if *e.kind() == IntErrorKind::PosOverflow => Err(SolveError::Overflow),
+            Err(e) => panic!("{e}: {s:?}"),
+        }
+    }
+
+    fn apply(op: Operation, acc: Self, x: Self) -> Result<Self, SolveError> {
+        match op {
+            Operation::Add => acc.checked_add(x).ok_or(SolveError::Overflow),
+            Operation::Mult => acc.checked_mul(x).ok_or(SolveError::Overflow),
+            Operation::Sub => acc.checked_sub(x).ok_or(SolveError::Overflow),
+            Operation::Div => acc.checked_div(x).ok_or(SolveError::DivisionByZero),
+            Operation::Pow => match u32::try_from(x) {
+                Ok(exp) => acc.checked_pow(exp).ok_or(SolveError::Overflow),
+                // 0 and 1 are the only bases that survive a huge exponent
+                Err(_) if acc <= 1 => Ok(acc),
+                Err(_) => Err(SolveError::Overflow),
+            },
+            Operation::Min => Ok(acc.min(x)),
+            Operation::Max => Ok(acc.max(x)),
+        }
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        self.checked_add(other)
+    }
 }
 
+// the most bits a power of big integers can have, a few megabytes
+const MAX_POW_BITS: u64 = 1 << 24;
+
+impl Value for BigInt {
+    fn parse(s: &str) -> Result<Self, SolveError> {
+        Ok(s.trim().parse().unwrap())
+    }
+
+    fn apply(op: Operation, acc: Self, x: Self) -> Result<Self, SolveError> {
+        match op {
+            Operation::Add => Ok(acc + x),
+            Operation::Mult => Ok(acc * x),
+            Operation::Sub => Ok(acc - x),
+            Operation::Div if x.is_zero() => Err(SolveError::DivisionByZero),
+            Operation::Div => Ok(acc / x),
+            Operation::Pow if x < BigInt::zero() => Err(SolveError::NegativeExponent),
+            Operation::Pow => match x.to_u32() {
+                // the result has about `acc.bits() * exp` bits, which has to
+                // stay small enough to hold in memory
+                Some(exp) if acc.bits() * u64::from(exp) <= MAX_POW_BITS => Ok(acc.pow(exp)),
+                // 0, 1 and -1 are the only bases that survive a huge
+                // exponent, as they do for `u64`
+                _ if acc.is_zero() || acc == BigInt::from(1) => Ok(acc),
+                _ if acc == BigInt::from(-1) => Ok(if x.bit(0) { acc } else { -acc }),
+                _ => Err(SolveError::ExponentTooLarge),
+            },
+            Operation::Min => Ok(acc.min(x)),
+            Operation::Max => Ok(acc.max(x)),
+        }
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        Some(self + other)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum DigitOrder {
+    #[default]
+    TopToBottom,
+    BottomToTop,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum ColumnOrder {
+    #[default]
+    LeftToRight,
+    RightToLeft,
+}
+
+// how the vertical numbers of part B are read: the order of the digits within
+// each column, and the order the columns are folded in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Orientation {
+    digits: DigitOrder,
+    columns: ColumnOrder,
+}
+
+#[derive(Debug, Clone, Copy)]
 enum Part {
     A,
-    B,
+    B(Orientation),
+}
+
+// what a problem does with its numbers: fold them all with a single operation
+// or plug them into an expression
+#[derive(Debug, Clone)]
+enum Formula {
+    Fold(Operation),
+    Expr(Expr),
+}
+
+impl Formula {
+    fn parse(s: &str) -> Result<Self, expr::ParseExprError> {
+        match s.parse() {
+            Ok(op) => Ok(Formula::Fold(op)),
+            Err(_) => s.parse().map(Formula::Expr),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 struct Problem {
     args: Vec<String>,
-    op: Operation,
+    formula: Formula,
+    // the operator as it was written, kept so the problem can be rendered
+    op_cell: String,
 }
 
 impl Problem {
     fn new(inputs: &[String]) -> Self {
-        let mut args = vec![];
-        for i in 0..inputs.len() - 1 {
-            args.push(inputs[i].clone());
+        let args = inputs[..inputs.len() - 1].to_vec();
+        let op_cell = inputs[inputs.len() - 1].clone();
+        let formula = Formula::parse(op_cell.trim()).unwrap();
+
+        Self {
+            args,
+            formula,
+            op_cell,
         }
-        let op = inputs[inputs.len() - 1].trim().parse().unwrap();
+    }
 
-        Self { args, op }
+    fn solve<T: Value>(&self, part: Part) -> Result<T, SolveError> {
+        match part {
+            Part::A => self.fold(),
+            Part::B(orientation) => self.transposed(orientation).fold(),
+        }
     }
 
-    fn solve_a(&self) -> u64 {
-        match self.op {
-            Operation::Mult => self
-                .args
-                .iter()
-                .fold(1, |acc, x| acc * x.trim().parse::<u64>().unwrap()),
-            Operation::Add => self
-                .args
-                .iter()
-                .fold(0, |acc, x| acc + x.trim().parse::<u64>().unwrap()),
+    fn fold<T: Value>(&self) -> Result<T, SolveError> {
+        // blank cells come from padding out ragged lines and don't hold a
+        // number
+        let mut args = self
+            .args
+            .iter()
+            .filter(|x| !x.trim().is_empty())
+            .map(|x| T::parse(x));
+        match &self.formula {
+            Formula::Fold(op) => {
+                let first = args.next().ok_or(SolveError::NoArguments)??;
+                args.try_fold(first, |acc, x| T::apply(*op, acc, x?))
+            }
+            Formula::Expr(expr) => expr.eval(&args.collect::<Result<Vec<_>, _>>()?),
         }
     }
 
-    fn solve_b(&self) -> u64 {
+    fn transposed(&self, orientation: Orientation) -> Self {
         // have to take the transpose of all the strings first
         let bytes_arr: Vec<&[u8]> = self.args.iter().map(|s| s.as_bytes()).collect();
-        let mut transposed_bytes_arr = vec![vec![b'0'; bytes_arr.len()]; bytes_arr[0].len()];
+        // a worksheet that's only operators has no lines to transpose
+        let width = bytes_arr.first().map_or(0, |b| b.len());
+        let mut transposed_bytes_arr = vec![vec![b'0'; bytes_arr.len()]; width];
 
         for i in 0..bytes_arr.len() {
-            for j in 0..bytes_arr[0].len() {
+            for j in 0..width {
                 transposed_bytes_arr[j][i] = bytes_arr[i][j];
             }
         }
 
+        if orientation.digits == DigitOrder::BottomToTop {
+            transposed_bytes_arr.iter_mut().for_each(|b| b.reverse());
+        }
+        if orientation.columns == ColumnOrder::RightToLeft {
+            transposed_bytes_arr.reverse();
+        }
+
         Self {
             args: transposed_bytes_arr
                 .into_iter()
                 .map(|b| String::from_utf8(b).unwrap())
                 .collect(),
-            op: self.op,
+            formula: self.formula.clone(),
+            op_cell: self.op_cell.clone(),
         }
-        .solve_a()
     }
 }
 
+// how the columns of a worksheet are split up into problems
+#[derive(Debug, Clone, Copy)]
+enum Segmentation {
+    // each problem starts at the column of its operator in the last line, so
+    // operators have to be left aligned with their numbers
+    Operators,
+    // problems are separated by columns that are blank in every line, and the
+    // operator can be anywhere under its numbers
+    BlankColumns,
+}
+
 #[derive(Debug, Default, Clone)]
 struct Worksheet {
     problems: Vec<Problem>,
 }
 
 impl Worksheet {
-    // this is helper function which just reads the last name of the file
-    // and gets the offsets
-    fn get_offsets(fname: &Path) -> Vec<usize> {
-        let f = File::open(fname).unwrap();
-        let reader = BufReader::new(f);
-
-        let last_line = reader.lines().last().unwrap().unwrap();
-        // get the offsets by reading the location of the chars that can be read
-        // in as a operator in the last line of the file
+    // gets the offsets of each problem from the operator line. `width` is
+    // the length of the longest line, which may be longer than the operator
+    // line if it has had its trailing whitespace stripped
+    fn get_offsets(last_line: &str, width: usize) -> Vec<usize> {
+        // get the offsets by reading the location of the words that can be read
+        // in as a operator in the last line of the file. Operators can be
+        // more than one char long, e.g. `min`, or an expression like `a*b+c`
+        // as long as it doesn't contain spaces
         // we know its ascii so can be lazy about utf-8
-        let mut offsets: Vec<usize> = last_line
-            .bytes()
-            .enumerate()
-            .filter_map(|(i, b)| {
-                let as_str = str::from_utf8(std::slice::from_ref(&b)).ok()?;
-                let _: Operation = as_str.parse().ok()?;
-                Some(i)
+        let bytes = last_line.as_bytes();
+        let mut offsets: Vec<usize> = (0..bytes.len())
+            .filter(|&i| bytes[i] != b' ' && (i == 0 || bytes[i - 1] == b' '))
+            .filter(|&i| {
+                let word = last_line[i..].split(' ').next().unwrap();
+                Formula::parse(word).is_ok()
             })
             .collect();
 
-        offsets.push(last_line.len() + 1);
+        offsets.push(width + 1);
         offsets
     }
-    fn from_file(fname: impl AsRef<Path>) -> Self {
-        let offsets = Self::get_offsets(fname.as_ref());
-        let f = File::open(fname.as_ref()).unwrap();
-        let reader = BufReader::new(f);
 
-        let mut input_matrix: Vec<Vec<String>> = vec![];
-
-        for line in reader.lines() {
-            let line = line.unwrap();
-            let line_bytes = line.into_bytes();
-            // parse all the columns, effectively
-            let inputs = offsets
+    // gets the `[start, end)` column range of each problem, splitting on the
+    // columns that are blank in every line
+    fn get_blank_column_blocks(lines: &[String], width: usize) -> Vec<(usize, usize)> {
+        let is_blank = |i: usize| {
+            lines
                 .iter()
-                .zip(offsets[1..].iter())
-                .map(|(&start, &next_start)| {
-                    String::from_utf8(line_bytes[start..next_start - 1].to_owned()).unwrap()
-                })
-                .collect();
-            input_matrix.push(inputs);
-        }
+                .all(|line| line.as_bytes().get(i).is_none_or(|&b| b == b' '))
+        };
 
-        // transpose the matrix probably should use ndarray or other linear alg
-        // lib
-        let mut transposed_matrix =
-            vec![vec![String::new(); input_matrix.len()]; input_matrix[0].len()];
-        for i in 0..input_matrix.len() {
-            for j in 0..input_matrix[0].len() {
-                transposed_matrix[j][i] = input_matrix[i][j].to_string();
+        let mut blocks = vec![];
+        let mut start = None;
+        for i in 0..width {
+            match (start, is_blank(i)) {
+                (None, false) => start = Some(i),
+                (Some(s), true) => {
+                    blocks.push((s, i));
+                    start = None;
+                }
+                _ => {}
             }
         }
+        if let Some(s) = start {
+            blocks.push((s, width));
+        }
+        blocks
+    }
+
+    fn from_file(fname: impl AsRef<Path>, segmentation: Segmentation) -> Self {
+        Self::from_reader(File::open(fname.as_ref()).unwrap(), segmentation)
+    }
+
+    // reads the worksheet in a single pass so it works on stdin too. The
+    // operators are on the last line so all the lines have to be buffered
+    // before any of them can be split into problems
+    fn from_reader(reader: impl Read, segmentation: Segmentation) -> Self {
+        let mut lines: Vec<String> = BufReader::new(reader)
+            .lines()
+            .map(|line| line.unwrap())
+            .collect();
+        while lines.last().is_some_and(|line| line.trim().is_empty()) {
+            lines.pop();
+        }
 
-        let problems = transposed_matrix.iter().map(|m| Problem::new(&m)).collect();
+        let width = lines.iter().map(|line| line.len()).max().unwrap();
+        let blocks = match segmentation {
+            Segmentation::Operators => Self::get_offsets(lines.last().unwrap(), width)
+                .windows(2)
+                .map(|w| (w[0], w[1] - 1))
+                .collect(),
+            Segmentation::BlankColumns => Self::get_blank_column_blocks(&lines, width),
+        };
+
+        // slice each problem's column straight out of every line, padding
+        // out any lines that are too short to reach it
+        let problems = blocks
+            .into_iter()
+            .map(|(start, end)| {
+                let inputs: Vec<String> = lines
+                    .iter()
+                    .map(|line| {
+                        let cell = line.get(start..end.min(line.len())).unwrap_or("");
+                        format!("{cell:<0$}", end - start)
+                    })
+                    .collect();
+                Problem::new(&inputs)
+            })
+            .collect();
         Self { problems }
     }
 
-    fn sum(&self, part: Part) -> u64 {
-        match part {
-            Part::A => self.problems.iter().map(|p| p.solve_a()).sum(),
-            Part::B => self.problems.iter().map(|p| p.solve_b()).sum(),
+    fn sum(&self, part: Part) -> Result<u64, ProblemError> {
+        self.total(part)
+    }
+
+    // exact version of `sum` that can't overflow
+    fn sum_big(&self, part: Part) -> Result<BigInt, ProblemError> {
+        self.total(part)
+    }
+
+    // prints the worksheet back out column by column with the part A and
+    // part B result of each problem underneath it. Columns are widened if a
+    // result doesn't fit under its problem. If `highlight` is set, the digits
+    // of each vertical part B number are given their own color
+    fn render(&self, orientation: Orientation, highlight: bool) -> String {
+        // each vertical number gets the next color in the cycle so that
+        // neighbouring numbers always look different
+        const COLORS: [u8; 6] = [31, 32, 33, 34, 35, 36];
+
+        let result = |p: &Problem, part| match p.solve::<BigInt>(part) {
+            Ok(value) => value.to_string(),
+            Err(e) => e.to_string(),
+        };
+        let results: Vec<(String, String)> = self
+            .problems
+            .iter()
+            .map(|p| (result(p, Part::A), result(p, Part::B(orientation))))
+            .collect();
+        let widths: Vec<usize> = self
+            .problems
+            .iter()
+            .zip(results.iter())
+            .map(|(p, (a, b))| p.op_cell.len().max(a.len()).max(b.len()))
+            .collect();
+
+        // cells are paired with their visible length, which doesn't include
+        // any escape codes
+        let plain = |cell: String| {
+            let len = cell.len();
+            (cell, len)
+        };
+        let mut out = String::new();
+        let mut push_row = |label: &str, cells: Vec<(String, usize)>| {
+            let mut row = format!("{label:<3}");
+            for (i, ((cell, len), width)) in cells.into_iter().zip(widths.iter()).enumerate() {
+                if i > 0 {
+                    row.push(' ');
+                }
+                row.push_str(&cell);
+                row.push_str(&" ".repeat(width - len));
+            }
+            out.push_str(row.trim_end());
+            out.push('\n');
+        };
+
+        let num_rows = self.problems.first().map_or(0, |p| p.args.len());
+        for i in 0..num_rows {
+            let cells = self
+                .problems
+                .iter()
+                .map(|p| {
+                    let cell = &p.args[i];
+                    if !highlight {
+                        return plain(cell.clone());
+                    }
+                    let colored = cell
+                        .chars()
+                        .enumerate()
+                        .map(|(j, c)| match c {
+                            ' ' => c.to_string(),
+                            _ => format!("\x1b[{}m{c}\x1b[0m", COLORS[j % COLORS.len()]),
+                        })
+                        .collect();
+                    (colored, cell.len())
+                })
+                .collect();
+            push_row("", cells);
         }
+        let ops = self.problems.iter().map(|p| plain(p.op_cell.clone()));
+        push_row("", ops.collect());
+        let lines = widths.iter().map(|&width| plain("-".repeat(width)));
+        push_row("", lines.collect());
+        let part_a = results.iter().map(|(a, _)| plain(a.clone()));
+        push_row("A:", part_a.collect());
+        let part_b = results.iter().map(|(_, b)| plain(b.clone()));
+        push_row("B:", part_b.collect());
+        out
+    }
+
+    fn total<T: Value>(&self, part: Part) -> Result<T, ProblemError> {
+        let mut total = T::parse("0").unwrap();
+        for (i, p) in self.problems.iter().enumerate() {
+            let to_err = |error| ProblemError { problem: i, error };
+            let result = p.solve(part).map_err(to_err)?;
+            total = total
+                .checked_add(result)
+                .ok_or(to_err(SolveError::Overflow))?;
+        }
+        Ok(total)
     }
 }
 
 fn main() {
-    let worksheet = Worksheet::from_file("input.txt");
-    println!("Part A: `{}`", worksheet.sum(Part::A));
-    println!("Part B: `{}`", worksheet.sum(Part::B));
+    // pass `-` to read the worksheet from stdin, and `--blank-columns` to split
+    // problems on blank columns rather than operator positions.
+    // `--bottom-to-top` and `--right-to-left` change how part B is read.
+    // `--render` prints the worksheet with its results, and `--highlight`
+    // colors the part B numbers when rendering
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let has_flag = |flag: &str| args.iter().any(|arg| arg == flag);
+    let segmentation = if has_flag("--blank-columns") {
+        Segmentation::BlankColumns
+    } else {
+        Segmentation::Operators
+    };
+    let orientation = Orientation {
+        digits: if has_flag("--bottom-to-top") {
+            DigitOrder::BottomToTop
+        } else {
+            DigitOrder::TopToBottom
+        },
+        columns: if has_flag("--right-to-left") {
+            ColumnOrder::RightToLeft
+        } else {
+            ColumnOrder::LeftToRight
+        },
+    };
+    let worksheet = match args
+        .iter()
+        .find(|arg| !arg.starts_with("--"))
+        .map(String::as_str)
+    {
+        Some("-") => Worksheet::from_reader(io::stdin(), segmentation),
+        Some(fname) => Worksheet::from_file(fname, segmentation),
+        None => Worksheet::from_file("input.txt", segmentation),
+    };
+    if has_flag("--render") {
+        print!("{}", worksheet.render(orientation, has_flag("--highlight")));
+    }
+    for (name, part) in [("A", Part::A), ("B", Part::B(orientation))] {
+        match worksheet.sum(part) {
+            Ok(sum) => println!("Part {name}: `{sum}`"),
+            Err(ProblemError {
+                error: SolveError::Overflow,
+                problem,
+            }) => {
+                eprintln!("Part {name}: problem {problem} overflowed, using big integers");
+                println!("Part {name}: `{}`", worksheet.sum_big(part).unwrap());
+            }
+            Err(e) => panic!("Part {name}: {e}"),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -153,13 +540,402 @@ mod tests {
 
     #[test]
     fn test_a() {
-        let ws = Worksheet::from_file("test.txt");
-        assert_eq!(4277556, ws.sum(Part::A))
+        let ws = Worksheet::from_file("test.txt", Segmentation::Operators);
+        assert_eq!(Ok(4277556), ws.sum(Part::A))
     }
 
     #[test]
     fn test_b() {
-        let ws = Worksheet::from_file("test.txt");
-        assert_eq!(3263827, ws.sum(Part::B))
+        let ws = Worksheet::from_file("test.txt", Segmentation::Operators);
+        assert_eq!(Ok(3263827), ws.sum(Part::B(Orientation::default())))
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let input = std::fs::read("test.txt").unwrap();
+        let ws = Worksheet::from_reader(input.as_slice(), Segmentation::Operators);
+        assert_eq!(4, ws.problems.len());
+        assert_eq!(Ok(4277556), ws.sum(Part::A));
+        assert_eq!(Ok(3263827), ws.sum(Part::B(Orientation::default())));
+    }
+
+    #[test]
+    fn test_ragged() {
+        // same as test.txt with the trailing whitespace stripped from every
+        // line, and a trailing blank line
+        let input = "123 328  51 64\n 45 64  387 23\n  6 98  215 314\n*   +   *   +\n\n";
+        let ws = Worksheet::from_reader(input.as_bytes(), Segmentation::Operators);
+        assert_eq!(4, ws.problems.len());
+        assert_eq!(Ok(4277556), ws.sum(Part::A));
+        assert_eq!(Ok(3263827), ws.sum(Part::B(Orientation::default())));
+
+        // lines that don't reach the later problems at all
+        let input = "1 2\n3\n+ *";
+        let ws = Worksheet::from_reader(input.as_bytes(), Segmentation::Operators);
+        assert_eq!(2, ws.problems.len());
+        assert_eq!(Ok(4 + 2), ws.sum(Part::A));
+    }
+
+    #[test]
+    fn test_no_arguments() {
+        // no line reaches the middle problem
+        let input = "1   2\n3    \n+ * +";
+        let ws = Worksheet::from_reader(input.as_bytes(), Segmentation::Operators);
+        assert_eq!(3, ws.problems.len());
+        let err = ProblemError {
+            problem: 1,
+            error: SolveError::NoArguments,
+        };
+        assert_eq!(Err(err), ws.sum(Part::A));
+        assert_eq!(Err(err), ws.sum_big(Part::B(Orientation::default())));
+
+        // nothing but operators
+        let ws = Worksheet::from_reader("+ *".as_bytes(), Segmentation::Operators);
+        let err = ProblemError {
+            problem: 0,
+            error: SolveError::NoArguments,
+        };
+        assert_eq!(Err(err), ws.sum(Part::A));
+        assert_eq!(Err(err), ws.sum(Part::B(Orientation::default())));
+    }
+
+    #[test]
+    fn test_blank_columns() {
+        for fname in ["test.txt", "test_ops.txt"] {
+            let by_ops = Worksheet::from_file(fname, Segmentation::Operators);
+            let by_blanks = Worksheet::from_file(fname, Segmentation::BlankColumns);
+            assert_eq!(by_ops.sum(Part::A), by_blanks.sum(Part::A));
+            assert_eq!(
+                by_ops.sum(Part::B(Orientation::default())),
+                by_blanks.sum(Part::B(Orientation::default()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_blank_columns_unaligned_operators() {
+        let input = "123 328  51 64 \n 45 64  387 23 \n  6 98  215 314\n  *  +    * +  \n";
+        let ws = Worksheet::from_reader(input.as_bytes(), Segmentation::BlankColumns);
+        assert_eq!(4, ws.problems.len());
+        assert_eq!(Ok(4277556), ws.sum(Part::A));
+        assert_eq!(Ok(3263827), ws.sum(Part::B(Orientation::default())));
+    }
+
+    #[test]
+    fn test_blank_column_blocks() {
+        // the first line is too short to reach the last problem
+        let lines: Vec<String> = ["12  3", " 4    56", "+  *   +"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            vec![(0, 2), (3, 5), (6, 8)],
+            Worksheet::get_blank_column_blocks(&lines, 8)
+        );
+    }
+
+    #[test]
+    fn test_expr_file() {
+        for segmentation in [Segmentation::Operators, Segmentation::BlankColumns] {
+            let ws = Worksheet::from_file("test_expr.txt", segmentation);
+            assert_eq!(4, ws.problems.len());
+            assert_eq!(
+                Ok((123 * 45 + 6) + (12 + 345) * 6 + (50 - 20 * 2) + 6),
+                ws.sum(Part::A)
+            );
+            assert_eq!(
+                Ok((146 * 25 + 3) + (136 + 24) * 5 + 52 + 123),
+                ws.sum(Part::B(Orientation::default()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_expr_missing_argument() {
+        let p = problem(&["1", "2", "a+b+c"]);
+        assert_eq!(Err(SolveError::MissingArgument), p.solve::<u64>(Part::A));
+    }
+
+    #[test]
+    fn test_render() {
+        let ws = Worksheet::from_file("test.txt", Segmentation::Operators);
+        let expected = [
+            "   123   328  51     64",
+            "    45   64  387     23",
+            "     6   98  215     314",
+            "   *     +   *       +",
+            "   ----- --- ------- ----",
+            "A: 33210 490 4243455 401",
+            "B: 8544  625 3253600 1058",
+            "",
+        ]
+        .join("\n");
+        assert_eq!(expected, ws.render(Orientation::default(), false));
+    }
+
+    #[test]
+    fn test_render_highlight() {
+        let ws = Worksheet::from_file("test.txt", Segmentation::Operators);
+        let rendered = ws.render(Orientation::default(), true);
+        let first_row = rendered.lines().next().unwrap();
+        // 1, 2 and 3 are in different vertical numbers so get different colors
+        assert!(first_row.starts_with("   \x1b[31m1\x1b[0m\x1b[32m2\x1b[0m\x1b[33m3\x1b[0m"));
+        // with the escape codes stripped it's the same as the plain render
+        let stripped = rendered
+            .replace("\x1b[0m", "")
+            .replace("\x1b[31m", "")
+            .replace("\x1b[32m", "")
+            .replace("\x1b[33m", "");
+        assert_eq!(ws.render(Orientation::default(), false), stripped);
+    }
+
+    fn problem(inputs: &[&str]) -> Problem {
+        let inputs: Vec<String> = inputs.iter().map(|s| s.to_string()).collect();
+        Problem::new(&inputs)
+    }
+
+    #[test]
+    fn test_sub() {
+        let p = problem(&["98", "61", "5 ", "- "]);
+        assert_eq!(Ok(32), p.solve::<u64>(Part::A));
+        assert_eq!(
+            Ok(965 - 81),
+            p.solve::<u64>(Part::B(Orientation::default()))
+        );
+    }
+
+    #[test]
+    fn test_div() {
+        let p = problem(&["842", "21 ", "2  ", "/  "]);
+        assert_eq!(Ok(20), p.solve::<u64>(Part::A));
+        assert_eq!(
+            Ok(822 / 41 / 2),
+            p.solve::<u64>(Part::B(Orientation::default()))
+        );
+    }
+
+    #[test]
+    fn test_div_by_zero() {
+        let p = problem(&["10", " 0", "/ "]);
+        assert_eq!(Err(SolveError::DivisionByZero), p.solve::<u64>(Part::A));
+        assert_eq!(
+            Err(SolveError::DivisionByZero),
+            p.solve::<u64>(Part::B(Orientation::default()))
+        );
+        let p = problem(&["10", "10", "/ "]);
+        assert_eq!(Ok(1), p.solve::<u64>(Part::A));
+        assert_eq!(
+            Err(SolveError::DivisionByZero),
+            p.solve::<u64>(Part::B(Orientation::default()))
+        );
+    }
+
+    #[test]
+    fn test_orientation() {
+        let p = problem(&["98", "61", "5 ", "- "]);
+        let bottom_to_top = Orientation {
+            digits: DigitOrder::BottomToTop,
+            columns: ColumnOrder::LeftToRight,
+        };
+        assert_eq!(Ok(569 - 18), p.solve::<u64>(Part::B(bottom_to_top)));
+
+        // big ints so the reversed subtractions can go negative
+        let p = problem(&["19", "8 ", "- "]);
+        let right_to_left = Orientation {
+            digits: DigitOrder::TopToBottom,
+            columns: ColumnOrder::RightToLeft,
+        };
+        let both = Orientation {
+            digits: DigitOrder::BottomToTop,
+            columns: ColumnOrder::RightToLeft,
+        };
+        let solve = |orientation| p.solve::<BigInt>(Part::B(orientation)).unwrap();
+        assert_eq!(BigInt::from(18 - 9), solve(Orientation::default()));
+        assert_eq!(BigInt::from(9 - 18), solve(right_to_left));
+        assert_eq!(BigInt::from(81 - 9), solve(bottom_to_top));
+        assert_eq!(BigInt::from(9 - 81), solve(both));
+    }
+
+    #[test]
+    fn test_orientation_commutative() {
+        // only + and * in test.txt so the column order doesn't matter
+        let ws = Worksheet::from_file("test.txt", Segmentation::Operators);
+        let right_to_left = Orientation {
+            digits: DigitOrder::TopToBottom,
+            columns: ColumnOrder::RightToLeft,
+        };
+        assert_eq!(Ok(3263827), ws.sum(Part::B(right_to_left)));
+    }
+
+    #[test]
+    fn test_pow() {
+        let p = problem(&["2", "3", "2", "^"]);
+        assert_eq!(Ok(64), p.solve::<u64>(Part::A));
+        assert_eq!(Ok(232), p.solve::<u64>(Part::B(Orientation::default())));
+    }
+
+    #[test]
+    fn test_min() {
+        let p = problem(&["500", "120", "700", "min"]);
+        assert_eq!(Ok(120), p.solve::<u64>(Part::A));
+        assert_eq!(Ok(0), p.solve::<u64>(Part::B(Orientation::default())));
+    }
+
+    #[test]
+    fn test_max() {
+        let p = problem(&["500", "120", "700", "max"]);
+        assert_eq!(Ok(700), p.solve::<u64>(Part::A));
+        assert_eq!(Ok(517), p.solve::<u64>(Part::B(Orientation::default())));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let p = problem(&["4294967296", "4294967296", "*         "]);
+        assert_eq!(Err(SolveError::Overflow), p.solve::<u64>(Part::A));
+        assert_eq!(Ok(BigInt::from(1u128 << 64)), p.solve::<BigInt>(Part::A));
+
+        let p = problem(&["1", "2", "- "]);
+        assert_eq!(Err(SolveError::Overflow), p.solve::<u64>(Part::A));
+        assert_eq!(Ok(BigInt::from(-1)), p.solve::<BigInt>(Part::A));
+
+        let p = problem(&["2", "64", "^ "]);
+        assert_eq!(Err(SolveError::Overflow), p.solve::<u64>(Part::A));
+        let p = problem(&["2", "99999999999", "^          "]);
+        assert_eq!(Err(SolveError::Overflow), p.solve::<u64>(Part::A));
+        assert_eq!(
+            Err(SolveError::ExponentTooLarge),
+            p.solve::<BigInt>(Part::A)
+        );
+    }
+
+    #[test]
+    fn test_huge_exponents() {
+        // both modes agree wherever the checked one succeeds
+        for base in ["0", "1"] {
+            let p = problem(&[base, "99999999999", "^          "]);
+            let expected = p.solve::<u64>(Part::A).map(BigInt::from);
+            assert_eq!(Ok(BigInt::from(base.parse::<u64>().unwrap())), expected);
+            assert_eq!(expected, p.solve::<BigInt>(Part::A));
+        }
+        // -1 can only come up in big integers
+        let pow = |base: i64, exp: u64| {
+            BigInt::apply(Operation::Pow, BigInt::from(base), BigInt::from(exp))
+        };
+        assert_eq!(Ok(BigInt::from(-1)), pow(-1, 99999999999));
+        assert_eq!(Ok(BigInt::from(1)), pow(-1, 99999999998));
+    }
+
+    #[test]
+    fn test_pow_too_big() {
+        // an exponent that fits in a u32 can still give a result far too big
+        // to hold
+        let p = problem(&["99999999999999999999", "4000000000          ", "^"]);
+        assert_eq!(Err(SolveError::Overflow), p.solve::<u64>(Part::A));
+        assert_eq!(
+            Err(SolveError::ExponentTooLarge),
+            p.solve::<BigInt>(Part::A)
+        );
+        let pow = |base: i64, exp: u64| {
+            BigInt::apply(Operation::Pow, BigInt::from(base), BigInt::from(exp))
+        };
+        assert_eq!(Ok(BigInt::from(1) << 100000), pow(2, 100000));
+        // 3 takes two bits, so its powers stop just past 2^23
+        assert_eq!(
+            Err(SolveError::ExponentTooLarge),
+            pow(3, (1 << 23) + 1).map(|_| ())
+        );
+        // bases that don't grow still take any exponent
+        assert_eq!(Ok(BigInt::from(-1)), pow(-1, 4000000001));
+        assert_eq!(Ok(BigInt::from(1)), pow(0, 0));
+    }
+
+    #[test]
+    fn test_parse_overflow() {
+        // a single number too long for a u64
+        let p = problem(&["12345678901234567890123", "2                      ", "*"]);
+        assert_eq!(Err(SolveError::Overflow), p.solve::<u64>(Part::A));
+        assert_eq!(
+            Ok("24691357802469135780246".parse::<BigInt>().unwrap()),
+            p.solve::<BigInt>(Part::A)
+        );
+
+        // a column of 21 nines read downwards is a 21 digit number
+        let mut inputs = vec!["9"; 21];
+        inputs.push("*");
+        let p = problem(&inputs);
+        let b = Part::B(Orientation::default());
+        assert_eq!(Err(SolveError::Overflow), p.solve::<u64>(b));
+        let nines: BigInt = "9".repeat(21).parse().unwrap();
+        assert_eq!(Ok(nines.clone()), p.solve::<BigInt>(b));
+
+        // and the whole sheet falls back to big integers
+        let ws = Worksheet { problems: vec![p] };
+        assert_eq!(
+            Err(ProblemError {
+                problem: 0,
+                error: SolveError::Overflow
+            }),
+            ws.sum(b)
+        );
+        assert_eq!(Ok(nines), ws.sum_big(b));
+    }
+
+    #[test]
+    fn test_negative_exponent() {
+        assert_eq!(
+            Err(SolveError::NegativeExponent),
+            BigInt::apply(Operation::Pow, BigInt::from(2), BigInt::from(-1))
+        );
+    }
+
+    #[test]
+    fn test_sum_reports_problem() {
+        let mut ws = Worksheet::from_file("test.txt", Segmentation::Operators);
+        ws.problems
+            .push(problem(&["18446744073709551615", "2", "*"]));
+        assert_eq!(
+            Err(ProblemError {
+                problem: 4,
+                error: SolveError::Overflow
+            }),
+            ws.sum(Part::A)
+        );
+        assert_eq!(
+            Ok(BigInt::from(4277556) + BigInt::from(u64::MAX) * 2),
+            ws.sum_big(Part::A)
+        );
+
+        // the running total can overflow even if every problem fits
+        ws.problems.pop();
+        ws.problems
+            .push(problem(&["18446744073709551615", "1", "*"]));
+        assert_eq!(
+            Err(ProblemError {
+                problem: 4,
+                error: SolveError::Overflow
+            }),
+            ws.sum(Part::A)
+        );
+    }
+
+    #[test]
+    fn test_big_matches_checked() {
+        let ws = Worksheet::from_file("test.txt", Segmentation::Operators);
+        assert_eq!(Ok(BigInt::from(4277556)), ws.sum_big(Part::A));
+        assert_eq!(
+            Ok(BigInt::from(3263827)),
+            ws.sum_big(Part::B(Orientation::default()))
+        );
+    }
+
+    #[test]
+    fn test_ops_file() {
+        let ws = Worksheet::from_file("test_ops.txt", Segmentation::Operators);
+        assert_eq!(5, ws.problems.len());
+        assert_eq!(Ok(32 + 20 + 64 + 120 + 700), ws.sum(Part::A));
+        assert_eq!(
+            Ok(884 + 10 + 232 + 517),
+            ws.sum(Part::B(Orientation::default()))
+        );
     }
 }
@@ -0,0 +1,277 @@
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+
+use gif::{Encoder, Frame, Repeat};
+
+use crate::{Factory, TileType};
+
+// somewhere to send the state of the factory as `Factory::simulate_with`
+// steps the beams through it
+pub trait FrameSink {
+    // called before every step, and once more after the beams have all
+    // stopped
+    fn frame(&mut self, step: usize, factory: &Factory) -> io::Result<()>;
+
+    // called once after the last frame
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// throws the frames away
+pub struct NoFrames;
+
+impl FrameSink for NoFrames {
+    fn frame(&mut self, _step: usize, _factory: &Factory) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// the plain map for each step, as `simulate(true)` prints it
+pub struct TextFrames<W>(pub W);
+
+impl<W: Write> FrameSink for TextFrames<W> {
+    fn frame(&mut self, step: usize, factory: &Factory) -> io::Result<()> {
+        writeln!(self.0, "step {step}")?;
+        writeln!(self.0, "num of paths {}", factory.beams.len())?;
+        writeln!(self.0, "{}", factory.get_map())
+    }
+}
+
+// the number of different brightnesses a beam can be drawn in
+const LEVELS: u8 = 8;
+
+// how bright the beam on each tile is, from 1 to `LEVELS`, or 0 if there's no
+// beam moving through it. Brightness goes with the log of the number of
+// timelines on the tile, so the busiest tile in the frame is the brightest
+fn intensities(factory: &Factory) -> Vec<Vec<u8>> {
+    let mut bits: Vec<Vec<u64>> = factory.floor.iter().map(|row| vec![0; row.len()]).collect();
+    for beam in factory.beams.iter().filter(|b| b.is_active) {
+        let tile = &mut bits[beam.y][beam.x];
+        *tile = (*tile).max(beam.num_of_paths.bits());
+    }
+    let max = bits.iter().flatten().copied().max().unwrap_or(0);
+    bits.iter()
+        .map(|row| {
+            row.iter()
+                .map(|&b| match b {
+                    0 => 0,
+                    b if b == max => LEVELS,
+                    b => 1 + ((b - 1) * u64::from(LEVELS - 1) / (max - 1).max(1)) as u8,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// the colours of the tiles without a beam on them, in the order
+// `tile_color` numbers them
+const TILE_COLORS: [[u8; 3]; 7] = [
+    [40, 40, 40],    // empty
+    [60, 60, 110],   // where a beam has been
+    [120, 120, 120], // splitter
+    [240, 240, 240], // splitter that's been hit
+    [80, 200, 80],   // source
+    [80, 180, 220],  // mirror or filter
+    [150, 60, 60],   // absorber
+];
+
+fn tile_color(tile: TileType) -> u8 {
+    match tile {
+        TileType::Empty => 0,
+        TileType::Beam => 1,
+        TileType::Splitter { been_hit: false } => 2,
+        TileType::Splitter { been_hit: true } => 3,
+        TileType::BeamSource { .. } => 4,
+        TileType::Mirror(_) | TileType::Filter { .. } => 5,
+        TileType::Absorber => 6,
+    }
+}
+
+// the tile colours then the beam colours, from a dim blue for the quietest
+// beams up to a bright yellow for the busiest
+fn palette() -> Vec<[u8; 3]> {
+    let (cold, hot) = ([60, 80, 220], [255, 230, 60]);
+    let beams = (0..LEVELS).map(|level| {
+        let lerp = |c: usize| {
+            let (from, to): (i32, i32) = (cold[c], hot[c]);
+            (from + (to - from) * i32::from(level) / i32::from(LEVELS - 1)) as u8
+        };
+        [lerp(0), lerp(1), lerp(2)]
+    });
+    TILE_COLORS.into_iter().chain(beams).collect()
+}
+
+// the index into `palette` a tile is drawn in, given the intensity of the
+// beam on it
+fn color(tile: TileType, intensity: u8) -> u8 {
+    match intensity {
+        0 => tile_color(tile),
+        i => TILE_COLORS.len() as u8 + i - 1,
+    }
+}
+
+// redraws the map in place in a terminal, colouring the beams by how many
+// timelines they carry, and waits `delay` after each frame
+pub struct AnsiFrames<W> {
+    pub out: W,
+    pub delay: Duration,
+}
+
+impl<W: Write> FrameSink for AnsiFrames<W> {
+    fn frame(&mut self, step: usize, factory: &Factory) -> io::Result<()> {
+        let palette = palette();
+        // clear the screen and go back to the top left
+        write!(self.out, "\x1b[2J\x1b[H")?;
+        writeln!(self.out, "step {step}")?;
+        for (row, intensity) in factory.floor.iter().zip(intensities(factory)) {
+            for (&tile, i) in row.iter().zip(intensity) {
+                let [r, g, b] = palette[usize::from(color(tile, i))];
+                write!(self.out, "\x1b[38;2;{r};{g};{b}m{}", char::from(tile))?;
+            }
+            writeln!(self.out, "\x1b[0m")?;
+        }
+        self.out.flush()?;
+        thread::sleep(self.delay);
+        Ok(())
+    }
+}
+
+// collects the frames into an animated GIF, drawing each tile as a `scale`
+// pixel square and showing each frame for `delay`
+pub struct GifFrames<W: Write> {
+    out: Option<W>,
+    encoder: Option<Encoder<W>>,
+    scale: usize,
+    delay: Duration,
+}
+
+impl<W: Write> GifFrames<W> {
+    pub fn new(out: W, scale: usize, delay: Duration) -> Self {
+        Self {
+            out: Some(out),
+            encoder: None,
+            scale,
+            delay,
+        }
+    }
+}
+
+impl<W: Write> FrameSink for GifFrames<W> {
+    fn frame(&mut self, _step: usize, factory: &Factory) -> io::Result<()> {
+        let height = factory.floor.len() * self.scale;
+        let width = factory.floor.iter().map(Vec::len).max().unwrap_or(0) * self.scale;
+        let too_big = || io::Error::other("the floor is too big for a GIF");
+        let (gif_width, gif_height) = (
+            u16::try_from(width).map_err(|_| too_big())?,
+            u16::try_from(height).map_err(|_| too_big())?,
+        );
+        // the size of the image isn't known until the first frame
+        if self.encoder.is_none() {
+            let out = self.out.take().expect("frames written after finishing");
+            let palette: Vec<u8> = palette().into_iter().flatten().collect();
+            let mut encoder =
+                Encoder::new(out, gif_width, gif_height, &palette).map_err(io::Error::other)?;
+            encoder
+                .set_repeat(Repeat::Infinite)
+                .map_err(io::Error::other)?;
+            self.encoder = Some(encoder);
+        }
+
+        // rows shorter than the longest are padded with empty floor
+        let mut pixels = vec![0; width * height];
+        for (y, (row, intensity)) in factory.floor.iter().zip(intensities(factory)).enumerate() {
+            for (x, (&tile, i)) in row.iter().zip(intensity).enumerate() {
+                let color = color(tile, i);
+                for py in y * self.scale..(y + 1) * self.scale {
+                    pixels[py * width + x * self.scale..][..self.scale].fill(color);
+                }
+            }
+        }
+        let mut frame = Frame::from_indexed_pixels(gif_width, gif_height, pixels, None);
+        // GIF delays are in hundredths of a second
+        frame.delay = (self.delay.as_millis() / 10).try_into().unwrap_or(u16::MAX);
+        let encoder = self.encoder.as_mut().unwrap();
+        encoder.write_frame(&frame).map_err(io::Error::other)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            encoder.into_inner().map_err(io::Error::other)?.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::triangle;
+
+    #[test]
+    fn test_text_frames() {
+        let mut out = vec![];
+        let factory = Factory::from_file("test.txt")
+            .simulate_with(&mut TextFrames(&mut out))
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("step 0\nnum of paths 1\n.......S.......\n"));
+        // one frame for each of the 16 steps down and off the floor, and
+        // one at the end
+        assert_eq!(17, out.matches("step").count());
+        assert!(out.ends_with(&format!("{}\n", factory.get_map())));
+    }
+
+    #[test]
+    fn test_intensities() {
+        let mut factory = triangle(3);
+        for _ in 0..5 {
+            factory.tick();
+        }
+        // the beams have been split twice, so the middle one carries two
+        // timelines and the outer ones one each
+        let beams: Vec<u8> = intensities(&factory)
+            .into_iter()
+            .flatten()
+            .filter(|&i| i > 0)
+            .collect();
+        assert_eq!(vec![1, LEVELS, 1], beams);
+    }
+
+    #[test]
+    fn test_ansi_frames() {
+        let mut out = vec![];
+        triangle(3)
+            .simulate_with(&mut AnsiFrames {
+                out: &mut out,
+                delay: Duration::ZERO,
+            })
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let [r, g, b] = palette()[usize::from(color(TileType::Beam, LEVELS))];
+        assert!(out.contains(&format!("\x1b[38;2;{r};{g};{b}m|")));
+        assert!(out.starts_with("\x1b[2J\x1b[Hstep 0\n"));
+    }
+
+    #[test]
+    fn test_gif_frames() {
+        let mut gif = vec![];
+        Factory::from_file("test.txt")
+            .simulate_with(&mut GifFrames::new(&mut gif, 3, Duration::from_millis(100)))
+            .unwrap();
+
+        let mut decoder = gif::Decoder::new(gif.as_slice()).unwrap();
+        assert_eq!((45, 48), (decoder.width(), decoder.height()));
+        let mut sources = vec![];
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(10, frame.delay);
+            // the source at (7, 0) is drawn in the top row of pixels
+            sources.push(frame.buffer[7 * 3]);
+        }
+        assert_eq!(17, sources.len());
+        // the beam starts out on the source, then leaves it behind
+        assert_eq!(color(TileType::Beam, LEVELS), sources[0]);
+        assert_eq!(tile_color(TileType::from('S')), sources[16]);
+    }
+}
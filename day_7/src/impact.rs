@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+use num_bigint::BigUint;
+
+use crate::{Factory, Run, Source};
+
+// how much a splitter matters to the manifold. Beams in the same place going
+// the same way are the same beam however many timelines they carry, so
+// `beams` counts the different places and directions it's hit from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitterImpact {
+    pub at: (usize, usize),
+    pub beams: usize,
+    // the number of timelines that go through the splitter, counting a
+    // timeline again each time it comes back from somewhere else
+    pub timelines: BigUint,
+}
+
+impl Factory {
+    // works out the impact of every splitter a beam hits, most timelines
+    // first. The timelines through a splitter are the timelines reaching
+    // each beam it splits times the timelines from there on, so a forward
+    // pass counts the first and the backward pass in `Timelines` the second.
+    //
+    // Panics if the beams loop, like `count_paths`
+    pub fn splitter_impact(&self) -> Vec<SplitterImpact> {
+        let timelines = self.timelines();
+        // the number of timelines reaching each beam that's about to be split
+        let mut reaching: HashMap<_, BigUint> = HashMap::new();
+        for &Source { x, y, dir } in &self.sources {
+            if let Run::Split { beam, .. } = self.run((x, y, dir)) {
+                *reaching.entry(beam).or_default() += 1u32;
+            }
+        }
+
+        let mut impact: HashMap<(usize, usize), SplitterImpact> = HashMap::new();
+        // backwards through the split order every beam comes after all the
+        // beams that split into it, so its count is complete by the time
+        // it's reached
+        for &beam in timelines.split_order().iter().rev() {
+            let Run::Split { at, sides, .. } = self.run(beam) else {
+                unreachable!("only split beams are in the split order");
+            };
+            let n = reaching.remove(&beam).unwrap_or_default();
+            let splitter = impact.entry(at).or_insert(SplitterImpact {
+                at,
+                beams: 0,
+                timelines: BigUint::default(),
+            });
+            splitter.beams += 1;
+            splitter.timelines += &n * timelines.count_from(beam);
+            for (x, y) in sides.into_iter().flatten() {
+                if let Run::Split { beam: next, .. } = self.run((x, y, beam.2)) {
+                    *reaching.entry(next).or_default() += &n;
+                }
+            }
+        }
+
+        let mut impact: Vec<_> = impact.into_values().collect();
+        impact.sort_by(|a, b| {
+            (&b.timelines, b.beams, a.at.1, a.at.0).cmp(&(&a.timelines, a.beams, b.at.1, b.at.0))
+        });
+        impact
+    }
+
+    // the `top` most important splitters, one a line
+    pub fn impact_report(&self, top: usize) -> String {
+        let mut report = String::new();
+        for (rank, splitter) in self.splitter_impact().iter().take(top).enumerate() {
+            let (x, y) = splitter.at;
+            report.push_str(&format!(
+                "{:>3}. splitter at ({x}, {y}): `{}` timelines, `{}` beams\n",
+                rank + 1,
+                splitter.timelines,
+                splitter.beams
+            ));
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{MULTI_SOURCE_MANIFOLD, big_manifold, mirror_manifold};
+    use crate::timelines::Choice;
+
+    // follows every timeline one at a time to find the splitters it goes
+    // through, and the beams that hit them
+    fn brute_force(factory: &Factory) -> Vec<SplitterImpact> {
+        let mut impact: HashMap<(usize, usize), (Vec<_>, BigUint)> = HashMap::new();
+        for timeline in factory.iter_timelines() {
+            let Source { x, y, dir } = factory.sources[timeline.source];
+            let mut beam = (x, y, dir);
+            for choice in timeline.choices {
+                let Run::Split {
+                    beam: split,
+                    at,
+                    sides,
+                } = factory.run(beam)
+                else {
+                    unreachable!();
+                };
+                let (beams, timelines) = impact.entry(at).or_default();
+                if !beams.contains(&split) {
+                    beams.push(split);
+                }
+                *timelines += 1u32;
+                match sides[usize::from(choice == Choice::Right)] {
+                    Some((x, y)) => beam = (x, y, split.2),
+                    None => break,
+                }
+            }
+        }
+        let mut impact: Vec<_> = impact
+            .into_iter()
+            .map(|(at, (beams, timelines))| SplitterImpact {
+                at,
+                beams: beams.len(),
+                timelines,
+            })
+            .collect();
+        impact.sort_by_key(|s| s.at);
+        impact
+    }
+
+    fn check(factory: &Factory) {
+        let impact = factory.splitter_impact();
+        for pair in impact.windows(2) {
+            assert!(pair[0].timelines >= pair[1].timelines);
+        }
+        let mut by_position = impact.clone();
+        by_position.sort_by_key(|s| s.at);
+        assert_eq!(brute_force(factory), by_position);
+        // every splitter that's hit is in there
+        assert_eq!(factory.parts().0.combined, impact.len());
+    }
+
+    #[test]
+    fn test_splitter_impact() {
+        let factory = Factory::from_file("test.txt");
+        let impact = factory.splitter_impact();
+        // every timeline goes through the first splitter
+        assert_eq!(
+            SplitterImpact {
+                at: (7, 2),
+                beams: 1,
+                timelines: BigUint::from(40u32)
+            },
+            impact[0]
+        );
+        check(&factory);
+    }
+
+    #[test]
+    fn test_splitter_impact_matches_brute_force() {
+        check(&Factory::from_reader(MULTI_SOURCE_MANIFOLD.as_bytes()));
+        check(&mirror_manifold('.'));
+        check(&Factory::from_reader(big_manifold(31, 30, 3).as_bytes()));
+    }
+
+    #[test]
+    fn test_beams_from_both_sides() {
+        // the splitter in the middle is hit by a beam from each source
+        let input = ["..S..", ".....", ">.^..", "....."].join("\n");
+        let factory = Factory::from_reader(input.as_bytes());
+        assert_eq!(2, factory.splitter_impact()[0].beams);
+        check(&factory);
+    }
+
+    #[test]
+    fn test_impact_report() {
+        let report = Factory::from_file("test.txt").impact_report(2);
+        assert_eq!(
+            Some("  1. splitter at (7, 2): `40` timelines, `1` beams"),
+            report.lines().next()
+        );
+        assert_eq!(2, report.lines().count());
+    }
+}
@@ -1,13 +1,102 @@
+mod frames;
+mod impact;
+mod timelines;
+
+use std::cell::OnceCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, Read};
 use std::path::Path;
+use std::time::Duration;
+
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+
+use frames::{AnsiFrames, FrameSink, GifFrames, NoFrames, TextFrames};
+
+// the way a beam is travelling across the floor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    fn delta(self) -> (isize, isize) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        }
+    }
+
+    // the two ways a splitter sends a beam travelling this way, left/up first
+    fn perpendicular(self) -> [Direction; 2] {
+        match self {
+            Direction::Up | Direction::Down => [Direction::Left, Direction::Right],
+            Direction::Left | Direction::Right => [Direction::Up, Direction::Down],
+        }
+    }
+
+    fn is_vertical(self) -> bool {
+        matches!(self, Direction::Up | Direction::Down)
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arrow = match self {
+            Direction::Up => '^',
+            Direction::Down => 'v',
+            Direction::Left => '<',
+            Direction::Right => '>',
+        };
+        write!(f, "{arrow}")
+    }
+}
+
+// `/` and `\` mirrors, named for the way they lean
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mirror {
+    Slash,
+    Backslash,
+}
+
+impl Mirror {
+    // the way a beam going `dir` leaves the mirror
+    fn reflect(self, dir: Direction) -> Direction {
+        match (self, dir) {
+            (Mirror::Slash, Direction::Right) | (Mirror::Backslash, Direction::Left) => {
+                Direction::Up
+            }
+            (Mirror::Slash, Direction::Left) | (Mirror::Backslash, Direction::Right) => {
+                Direction::Down
+            }
+            (Mirror::Slash, Direction::Down) | (Mirror::Backslash, Direction::Up) => {
+                Direction::Left
+            }
+            (Mirror::Slash, Direction::Up) | (Mirror::Backslash, Direction::Down) => {
+                Direction::Right
+            }
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 enum TileType {
     Empty,
     Splitter { been_hit: bool },
-    BeamSource,
+    BeamSource { dir: Direction },
     Beam,
+    Mirror(Mirror),
+    // stops any beam that runs into it
+    Absorber,
+    // lets beams going `admits` through and stops any others
+    Filter { admits: Direction },
 }
 
 impl From<char> for TileType {
@@ -15,8 +104,36 @@ impl From<char> for TileType {
         match c {
             '.' => TileType::Empty,
             '^' => TileType::Splitter { been_hit: false },
-            'S' => TileType::BeamSource,
+            // `S` is the original downward source. `^` is already taken by
+            // splitters, so upward sources are an `A`
+            'S' | 'v' => TileType::BeamSource {
+                dir: Direction::Down,
+            },
+            'A' => TileType::BeamSource { dir: Direction::Up },
+            '<' => TileType::BeamSource {
+                dir: Direction::Left,
+            },
+            '>' => TileType::BeamSource {
+                dir: Direction::Right,
+            },
             '|' => TileType::Beam,
+            '/' => TileType::Mirror(Mirror::Slash),
+            '\\' => TileType::Mirror(Mirror::Backslash),
+            '#' => TileType::Absorber,
+            // the arrows are taken by sources, so filters are the first
+            // letter of the way they let beams through
+            'U' => TileType::Filter {
+                admits: Direction::Up,
+            },
+            'D' => TileType::Filter {
+                admits: Direction::Down,
+            },
+            'L' => TileType::Filter {
+                admits: Direction::Left,
+            },
+            'R' => TileType::Filter {
+                admits: Direction::Right,
+            },
             _o => panic!("unexpected input"),
         }
     }
@@ -27,30 +144,143 @@ impl From<TileType> for char {
         match tile {
             TileType::Empty => '.',
             TileType::Splitter { .. } => '^',
-            TileType::BeamSource => 'S',
+            TileType::BeamSource { dir } => match dir {
+                Direction::Up => 'A',
+                Direction::Down => 'S',
+                Direction::Left => '<',
+                Direction::Right => '>',
+            },
             TileType::Beam => '|',
+            TileType::Mirror(Mirror::Slash) => '/',
+            TileType::Mirror(Mirror::Backslash) => '\\',
+            TileType::Absorber => '#',
+            TileType::Filter { admits } => match admits {
+                Direction::Up => 'U',
+                Direction::Down => 'D',
+                Direction::Left => 'L',
+                Direction::Right => 'R',
+            },
         }
     }
 }
 
+// what happens to a beam that a splitter sends off the side of the floor.
+// Both sides of the floor behave the same way
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum EdgeMode {
+    // the beam leaves the floor. It still counts as a timeline, it just
+    // doesn't go anywhere
+    #[default]
+    Lost,
+    // the beam bounces off the wall and joins the splitter's other beam
+    Reflect,
+    // the beam comes back in on the opposite side of the floor
+    Wrap,
+}
+
+impl EdgeMode {
+    // the column a beam split off a splitter in column `x` lands in, where
+    // `dx` is -1 for the left beam and 1 for the right. None if the beam is
+    // lost off the edge of a floor `width` wide. For beams travelling
+    // sideways the same goes for rows and the height of the floor
+    fn land(self, x: usize, dx: isize, width: usize) -> Option<usize> {
+        let in_bounds = |c: isize| usize::try_from(c).ok().filter(|&c| c < width);
+        let target = x as isize + dx;
+        in_bounds(target).or_else(|| match self {
+            EdgeMode::Lost => None,
+            EdgeMode::Reflect => in_bounds(x as isize - dx),
+            // on a one column floor the beam would wrap straight back into
+            // its splitter, so it's lost instead
+            EdgeMode::Wrap => Some(target.rem_euclid(width as isize) as usize).filter(|&c| c != x),
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 struct Beam {
     x: usize,
     y: usize,
+    dir: Direction,
     is_active: bool,
-    num_of_paths: usize,
+    num_of_paths: BigUint,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Source {
+    x: usize,
+    y: usize,
+    dir: Direction,
+}
+
+// where a beam at some point on the floor and going some way is on its next
+// step, worked out from the tile in front of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Move {
+    // it moves on to (x, y), going the way a mirror there turns it if any
+    To(usize, usize, Direction),
+    // the splitter at `at` splits it to either side. The new beams land next
+    // to the old one going the same way, or are lost off the floor (None)
+    Split {
+        at: (usize, usize),
+        sides: [Option<(usize, usize)>; 2],
+    },
+    // it leaves the floor or is absorbed, which ends its timeline
+    Stop,
+}
+
+// where a beam ends up once it's gone as far as it can without a splitter
+// giving it a choice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Run {
+    // it's at `beam`, about to be split by the splitter at `at`
+    Split {
+        beam: (usize, usize, Direction),
+        at: (usize, usize),
+        sides: [Option<(usize, usize)>; 2],
+    },
+    // it stopped at (x, y)
+    Stop(usize, usize),
+}
+
+// the results of a pass over the manifold: the number of distinct splitters
+// any beam hits (part a) and the number of timelines (part b). The timelines
+// double at every splitter so are counted exactly by default, or modulo
+// some number with `count_paths_mod`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PathCounts<T = BigUint> {
+    splitters_hit: usize,
+    timelines: T,
+}
+
+// an answer for each source on the floor, in reading order, and for the
+// floor as a whole. Splitters hit by more than one source only count once in
+// the combined total
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Totals<T> {
+    per_source: Vec<T>,
+    combined: T,
 }
 
 #[derive(Debug, Default, Clone)]
 struct Factory {
     floor: Vec<Vec<TileType>>,
+    sources: Vec<Source>,
     beams: Vec<Beam>,
+    edges: EdgeMode,
+    // what `find_loop` found, worked out the first time it's needed. Beams
+    // moving over the floor don't change it, but the edge mode does
+    loop_at: OnceCell<Option<(usize, usize, Direction)>>,
+    // what `can_loop` found, kept the same way
+    loop_possible: OnceCell<bool>,
 }
 
 impl Factory {
     fn from_file(fname: impl AsRef<Path>) -> Self {
-        let f = File::open(fname.as_ref()).unwrap();
-        let reader = BufReader::new(f);
+        Self::from_reader(File::open(fname.as_ref()).unwrap())
+    }
+
+    fn from_reader(reader: impl Read) -> Self {
+        let reader = BufReader::new(reader);
 
         let mut floor: Vec<Vec<TileType>> = vec![];
 
@@ -59,87 +289,260 @@ impl Factory {
             floor.push(line.chars().map(TileType::from).collect())
         }
 
-        let mut beams = vec![];
-        for y in 0..floor.len() {
-            for x in 0..floor[0].len() {
-                match floor[y][x] {
-                    TileType::BeamSource => beams.push(Beam {
-                        x,
-                        y,
-                        is_active: true,
-                        num_of_paths: 1,
-                    }),
+        let mut sources = vec![];
+        for (y, row) in floor.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                match tile {
+                    TileType::BeamSource { dir } => sources.push(Source { x, y, dir: *dir }),
                     _ => continue,
                 }
             }
         }
+        let beams = sources
+            .iter()
+            .map(|&Source { x, y, dir }| Beam {
+                x,
+                y,
+                dir,
+                is_active: true,
+                num_of_paths: BigUint::one(),
+            })
+            .collect();
 
-        Self { floor, beams }
+        Self {
+            floor,
+            sources,
+            beams,
+            edges: EdgeMode::default(),
+            loop_at: OnceCell::new(),
+            loop_possible: OnceCell::new(),
+        }
     }
 
-    fn tick(&mut self) {
-        let mut next_beams = vec![];
-        while let Some(Beam {
-            x,
-            y,
-            is_active,
-            num_of_paths,
-        }) = self.beams.pop()
-        {
-            let Some(next_pos) = self.floor.get_mut(y + 1).and_then(|row| row.get_mut(x)) else {
-                next_beams.push(Beam {
-                    x,
-                    y,
-                    is_active: false,
-                    num_of_paths,
-                });
-                continue;
-            };
-            match next_pos {
-                TileType::Splitter { .. } => {
-                    // handle some issues with undeflow... ahtough i think it actually will be fine
-                    if x == 0 {
-                        next_beams.push(Beam {
-                            x,
-                            y,
-                            is_active: false,
-                            num_of_paths,
-                        });
-                    } else {
-                        self.beams.push(Beam {
-                            x: x - 1,
-                            y,
-                            is_active,
-                            num_of_paths,
-                        });
+    fn with_edges(mut self, edges: EdgeMode) -> Self {
+        self.edges = edges;
+        self.loop_at = OnceCell::new();
+        self.loop_possible = OnceCell::new();
+        self
+    }
+
+    // the tile one step from (x, y) going `dir`, if it's on the floor
+    fn step(&self, x: usize, y: usize, dir: Direction) -> Option<(usize, usize)> {
+        let (dx, dy) = dir.delta();
+        let y = y.checked_add_signed(dy)?;
+        let x = x.checked_add_signed(dx)?;
+        self.floor.get(y)?.get(x)?;
+        Some((x, y))
+    }
+
+    // where a beam at (x, y) lands when a splitter sends it off to `side`
+    fn land(&self, x: usize, y: usize, side: Direction) -> Option<(usize, usize)> {
+        let (dx, dy) = side.delta();
+        if side.is_vertical() {
+            let y = self.edges.land(y, dy, self.floor.len())?;
+            Some((x, y))
+        } else {
+            let x = self.edges.land(x, dx, self.floor[y].len())?;
+            Some((x, y))
+        }
+    }
+
+    fn next_move(&self, x: usize, y: usize, dir: Direction) -> Move {
+        let Some((next_x, next_y)) = self.step(x, y, dir) else {
+            return Move::Stop;
+        };
+        match self.floor[next_y][next_x] {
+            TileType::Splitter { .. } => Move::Split {
+                at: (next_x, next_y),
+                sides: dir.perpendicular().map(|side| self.land(x, y, side)),
+            },
+            TileType::Mirror(mirror) => Move::To(next_x, next_y, mirror.reflect(dir)),
+            TileType::Absorber => Move::Stop,
+            TileType::Filter { admits } if admits != dir => Move::Stop,
+            _ => Move::To(next_x, next_y, dir),
+        }
+    }
+
+    // follows a beam going `dir` from (x, y) until it's split or stops. The
+    // beams mustn't loop
+    fn run(&self, (mut x, mut y, mut dir): (usize, usize, Direction)) -> Run {
+        loop {
+            match self.next_move(x, y, dir) {
+                Move::To(next_x, next_y, next_dir) => (x, y, dir) = (next_x, next_y, next_dir),
+                Move::Split { at, sides } => {
+                    return Run::Split {
+                        beam: (x, y, dir),
+                        at,
+                        sides,
+                    };
+                }
+                Move::Stop => return Run::Stop(x, y),
+            }
+        }
+    }
+
+    // the beams a beam going `dir` from (x, y) turns into on its next step,
+    // not counting the ones that stop
+    fn next_beams(
+        &self,
+        x: usize,
+        y: usize,
+        dir: Direction,
+    ) -> [Option<(usize, usize, Direction)>; 2] {
+        match self.next_move(x, y, dir) {
+            Move::To(x, y, dir) => [Some((x, y, dir)), None],
+            Move::Split { sides, .. } => sides.map(|side| side.map(|(x, y)| (x, y, dir))),
+            Move::Stop => [None, None],
+        }
+    }
+
+    // mirrors and splitters can send a beam back somewhere it's already
+    // been going the same way, after which it goes round forever. Returns
+    // the place and direction the loop comes back to, if any beam gets
+    // caught in one.
+    //
+    // Searches depth first from each source, keeping the beams on the
+    // current path so that running into one of them means a loop. The
+    // answer is kept, so only the first call does the search
+    fn find_loop(&self) -> Option<(usize, usize, Direction)> {
+        *self.loop_at.get_or_init(|| {
+            if self.can_loop() {
+                self.search_for_loop()
+            } else {
+                None
+            }
+        })
+    }
+
+    // without mirrors a beam never turns, and every move but a split takes
+    // it further the way it's going, so it can only come back to where it's
+    // been by being split from one splitter straight into another. False if
+    // there's no splitter that can do that, so there can't be a loop
+    fn can_loop(&self) -> bool {
+        *self.loop_possible.get_or_init(|| self.check_can_loop())
+    }
+
+    fn check_can_loop(&self) -> bool {
+        let has_mirrors = self
+            .floor
+            .iter()
+            .flatten()
+            .any(|tile| matches!(tile, TileType::Mirror(_)));
+        if has_mirrors {
+            return true;
+        }
+        let mut dirs: Vec<Direction> = self.sources.iter().map(|s| s.dir).collect();
+        dirs.sort_unstable();
+        dirs.dedup();
+        for (y, row) in self.floor.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                if !matches!(tile, TileType::Splitter { .. }) {
+                    continue;
+                }
+                for &dir in &dirs {
+                    // where a beam going `dir` is when it hits the splitter
+                    let (dx, dy) = dir.delta();
+                    let (Some(x), Some(y)) = (x.checked_add_signed(-dx), y.checked_add_signed(-dy))
+                    else {
+                        continue;
+                    };
+                    if self.floor.get(y).and_then(|row| row.get(x)).is_none() {
+                        continue;
                     }
+                    for side in dir.perpendicular() {
+                        if let Some((x, y)) = self.land(x, y, side)
+                            && let Some((x, y)) = self.step(x, y, dir)
+                            && matches!(self.floor[y][x], TileType::Splitter { .. })
+                        {
+                            return true;
+                        }
+                    }
+                }
+            }
+        }
+        false
+    }
 
-                    self.beams.push(Beam {
-                        x: x + 1,
-                        y,
-                        is_active,
-                        num_of_paths,
-                    });
-                    *next_pos = TileType::Splitter { been_hit: true }
+    fn search_for_loop(&self) -> Option<(usize, usize, Direction)> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Seen {
+            No,
+            OnPath,
+            Done,
+        }
+        let width = self.floor.iter().map(Vec::len).max().unwrap_or(0);
+        let index = |(x, y, dir): (usize, usize, Direction)| (y * width + x) * 4 + dir as usize;
+        let mut seen = vec![Seen::No; width * self.floor.len() * 4];
+        for &Source { x, y, dir } in &self.sources {
+            if seen[index((x, y, dir))] != Seen::No {
+                continue;
+            }
+            seen[index((x, y, dir))] = Seen::OnPath;
+            let mut path = vec![((x, y, dir), self.next_beams(x, y, dir))];
+            while let Some((beam, next)) = path.last_mut() {
+                let Some(next) = next.iter_mut().find_map(Option::take) else {
+                    seen[index(*beam)] = Seen::Done;
+                    path.pop();
+                    continue;
+                };
+                match seen[index(next)] {
+                    Seen::OnPath => return Some(next),
+                    Seen::Done => {}
+                    Seen::No => {
+                        seen[index(next)] = Seen::OnPath;
+                        let (x, y, dir) = next;
+                        path.push((next, self.next_beams(x, y, dir)));
+                    }
                 }
-                TileType::Beam => {
-                    let beam_to_merge = next_beams
-                        .iter_mut()
-                        .find(|b| b.x == x && b.y == y + 1)
-                        .unwrap();
+            }
+        }
+        None
+    }
 
-                    beam_to_merge.num_of_paths += num_of_paths;
+    fn tick(&mut self) {
+        let mut next_beams: Vec<Beam> = vec![];
+        // beams that end up in the same place going the same way are merged
+        let mut merged: HashMap<(usize, usize, Direction), usize> = HashMap::new();
+        while let Some(beam) = self.beams.pop() {
+            // beams that have stopped stay where they are
+            if !beam.is_active {
+                next_beams.push(beam);
+                continue;
+            }
+            match self.next_move(beam.x, beam.y, beam.dir) {
+                Move::To(x, y, dir) => {
+                    let tile = &mut self.floor[y][x];
+                    if let TileType::Empty = tile {
+                        *tile = TileType::Beam;
+                    }
+                    match merged.get(&(x, y, dir)) {
+                        Some(&i) => next_beams[i].num_of_paths += beam.num_of_paths,
+                        None => {
+                            merged.insert((x, y, dir), next_beams.len());
+                            next_beams.push(Beam { x, y, dir, ..beam });
+                        }
+                    }
                 }
-                TileType::Empty => {
-                    *next_pos = TileType::Beam;
-                    next_beams.push(Beam {
-                        x,
-                        y: y + 1,
-                        is_active,
-                        num_of_paths,
-                    })
+                Move::Split { at: (x, y), sides } => {
+                    self.floor[y][x] = TileType::Splitter { been_hit: true };
+                    for side in sides {
+                        let split = Beam {
+                            num_of_paths: beam.num_of_paths.clone(),
+                            ..beam
+                        };
+                        match side {
+                            Some((x, y)) => self.beams.push(Beam { x, y, ..split }),
+                            None => next_beams.push(Beam {
+                                is_active: false,
+                                ..split
+                            }),
+                        }
+                    }
                 }
-                TileType::BeamSource => unreachable!("Should only have 1 beam source"),
+                Move::Stop => next_beams.push(Beam {
+                    is_active: false,
+                    ..beam
+                }),
             }
         }
 
@@ -158,39 +561,421 @@ impl Factory {
         map
     }
 
-    fn simulate(mut self, debug: bool) -> Self {
+    // panics if the beams loop, as they'd never stop
+    fn simulate(self, debug: bool) -> Self {
+        let simulated = if debug {
+            self.simulate_with(&mut TextFrames(io::stdout()))
+        } else {
+            self.simulate_with(&mut NoFrames)
+        };
+        simulated.unwrap()
+    }
+
+    // steps the beams through the factory until they've all stopped, sending
+    // each step to `frames`
+    fn simulate_with(mut self, frames: &mut dyn FrameSink) -> io::Result<Self> {
+        self.assert_no_loops();
         let mut i = 0;
         while self.beams.iter().any(|b| b.is_active) {
-            if debug {
-                println!("step {i}");
-                println!("num of paths {}", self.beams.len());
-                println!("{}", self.get_map());
-            }
+            frames.frame(i, &self)?;
             self.tick();
             i += 1;
         }
+        frames.frame(i, &self)?;
+        frames.finish()?;
 
-        self
+        Ok(self)
     }
 
-    fn part_a(&self) -> usize {
-        self.floor
-            .iter()
-            .flat_map(|row| row.iter())
-            .filter(|t| matches!(t, TileType::Splitter { been_hit: true }))
-            .count()
+    fn assert_no_loops(&self) {
+        if let Some((x, y, dir)) = self.find_loop() {
+            panic!("beams loop forever through ({x}, {y}) going {dir}");
+        }
     }
 
-    fn part_b(&self) -> usize {
-        self.beams.iter().map(|b| b.num_of_paths).sum()
+    fn count_paths(&self) -> Totals<PathCounts> {
+        self.count_paths_with(BigUint::one(), |acc, n| *acc += n)
+    }
+
+    // counts the timelines modulo `modulus`, for when only the residue is
+    // needed and the exact count would be huge
+    fn count_paths_mod(&self, modulus: u64) -> Totals<PathCounts<u64>> {
+        assert!(modulus > 0, "modulus must be positive");
+        self.count_paths_with(1 % modulus, |acc, n| {
+            *acc = ((u128::from(*acc) + u128::from(n)) % u128::from(modulus)) as u64
+        })
+    }
+
+    // counts the splitters hit and the timelines for each source, and for
+    // all of them together. Gives the same answers as `simulate` but doesn't
+    // need to touch the floor, so it works whether or not the factory has
+    // been simulated.
+    //
+    // The counts start at `one` for each source and are summed with `add`.
+    // Panics if the beams loop, as there'd be infinitely many timelines
+    fn count_paths_with<T: Clone + Default + Zero>(
+        &self,
+        one: T,
+        add: impl Fn(&mut T, T),
+    ) -> Totals<PathCounts<T>> {
+        self.assert_no_loops();
+        let mut per_source = vec![];
+        let mut all_hit = HashSet::new();
+        let mut timelines = T::zero();
+        for &source in &self.sources {
+            let (hit, n) = self.count_source_with(source, one.clone(), &add);
+            add(&mut timelines, n.clone());
+            per_source.push(PathCounts {
+                splitters_hit: hit.len(),
+                timelines: n,
+            });
+            // with only one source there's nothing to deduplicate, which
+            // saves hashing every splitter on a big floor
+            if self.sources.len() > 1 {
+                all_hit.extend(hit);
+            }
+        }
+        let splitters_hit = match per_source.as_slice() {
+            [only] => only.splitters_hit,
+            _ => all_hit.len(),
+        };
+        Totals {
+            per_source,
+            combined: PathCounts {
+                splitters_hit,
+                timelines,
+            },
+        }
+    }
+
+    // counts the timelines from one source. Without anything that could
+    // make the beams loop, every beam from a source stays going the same way
+    // and a pass across the floor line by line is enough. Otherwise the beams
+    // are followed a step at a time.
+    //
+    // Returns the splitters hit, each once, and the number of timelines
+    fn count_source_with<T: Clone + Default + Zero>(
+        &self,
+        source: Source,
+        one: T,
+        add: &impl Fn(&mut T, T),
+    ) -> (Vec<(usize, usize)>, T) {
+        if self.can_loop() {
+            self.walk_source_with(source, one, add)
+        } else {
+            self.sweep_source_with(source, one, add)
+        }
+    }
+
+    // whether a beam going `dir` stops at the tile at (x, y) rather than
+    // going through it
+    fn stops(&self, x: usize, y: usize, dir: Direction) -> bool {
+        match self.floor[y][x] {
+            TileType::Absorber => true,
+            TileType::Filter { admits } => admits != dir,
+            _ => false,
+        }
+    }
+
+    // counts the timelines from one source in a single pass across the floor
+    // in the source's direction, keeping the number of timelines at each
+    // point of the current line rather than a list of beams. Splitting
+    // doesn't change a beam's direction, so every beam from the source is
+    // always on the same line.
+    //
+    // Only right when `can_loop` is false: a beam split off a splitter never
+    // meets another splitter on the splitter's line, so it goes straight on
+    // to the next line unless that first step stops it
+    fn sweep_source_with<T: Clone + Default + Zero>(
+        &self,
+        source: Source,
+        one: T,
+        add: &impl Fn(&mut T, T),
+    ) -> (Vec<(usize, usize)>, T) {
+        let Source { x, y, dir } = source;
+        let (height, width) = (self.floor.len(), self.floor[y].len());
+        // the lines are rows for beams going up or down and columns for beams
+        // going sideways, in the order the beams reach them. `i` is the
+        // position along a line
+        let (lines, i): (Vec<usize>, usize) = match dir {
+            Direction::Down => ((y + 1..height).collect(), x),
+            Direction::Up => ((0..y).rev().collect(), x),
+            Direction::Right => ((x + 1..width).collect(), y),
+            Direction::Left => ((0..x).rev().collect(), y),
+        };
+        let len = if dir.is_vertical() { width } else { height };
+        let at = |line: usize, i: usize| {
+            if dir.is_vertical() {
+                (i, line)
+            } else {
+                (line, i)
+            }
+        };
+
+        let mut hit = vec![];
+        let mut timelines = T::zero();
+        let mut paths = vec![T::zero(); len];
+        let mut next_paths = vec![T::zero(); len];
+        paths[i] = one;
+        // only the points between `lo` and `hi` can have any timelines on
+        // them, which saves scanning the whole line when the beams are narrow
+        let (mut lo, mut hi) = (i, i);
+        for line in lines {
+            let (mut next_lo, mut next_hi) = (len, 0);
+            // a beam that gets through the tile at `i` on this line is at `i`
+            // on the next one
+            let mut push = |i: usize, n: T, next_paths: &mut Vec<T>, timelines: &mut T| {
+                let (x, y) = at(line, i);
+                if self.stops(x, y, dir) {
+                    add(timelines, n);
+                } else {
+                    add(&mut next_paths[i], n);
+                    (next_lo, next_hi) = (next_lo.min(i), next_hi.max(i));
+                }
+            };
+            for (i, n) in paths.iter_mut().enumerate().take(hi + 1).skip(lo) {
+                // taking the count leaves `paths` zeroed, ready to be reused
+                // as `next_paths` for the following line
+                let n = std::mem::take(n);
+                if n.is_zero() {
+                    continue;
+                }
+                let (x, y) = at(line, i);
+                match self.floor[y][x] {
+                    TileType::Splitter { .. } => {
+                        hit.push((x, y));
+                        for di in [-1, 1] {
+                            match self.edges.land(i, di, len) {
+                                Some(i) => push(i, n.clone(), &mut next_paths, &mut timelines),
+                                // beams split off the edge of the floor are
+                                // lost, but they're still timelines
+                                None => add(&mut timelines, n.clone()),
+                            }
+                        }
+                    }
+                    _ => push(i, n, &mut next_paths, &mut timelines),
+                }
+            }
+            std::mem::swap(&mut paths, &mut next_paths);
+            if next_lo > next_hi {
+                break;
+            }
+            (lo, hi) = (next_lo, next_hi);
+        }
+        for n in paths {
+            add(&mut timelines, n);
+        }
+        (hit, timelines)
+    }
+
+    // counts the timelines from one source a step at a time, for floors
+    // where mirrors can turn the beams. Keeps the number of timelines for
+    // each place and direction a beam can be in, in an array indexed like
+    // `search_for_loop`, rather than a list of beams
+    fn walk_source_with<T: Clone + Default + Zero>(
+        &self,
+        source: Source,
+        one: T,
+        add: &impl Fn(&mut T, T),
+    ) -> (Vec<(usize, usize)>, T) {
+        const DIRECTIONS: [Direction; 4] = [
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+        ];
+        let Source { x, y, dir } = source;
+        let width = self.floor.iter().map(Vec::len).max().unwrap_or(0);
+        let index = |(x, y, dir): (usize, usize, Direction)| (y * width + x) * 4 + dir as usize;
+        let beam = |i: usize| ((i / 4) % width, i / 4 / width, DIRECTIONS[i % 4]);
+        let mut hit = vec![];
+        let mut was_hit = vec![false; width * self.floor.len()];
+        let mut timelines = T::zero();
+        // the beams for the next step, merged where they end up in the same
+        // place going the same way, and the indices of the ones in use
+        let mut counts = vec![T::zero(); width * self.floor.len() * 4];
+        let mut queued = vec![false; counts.len()];
+        let mut frontier = vec![index((x, y, dir))];
+        counts[frontier[0]] = one;
+        let mut next_frontier = vec![];
+        while !frontier.is_empty() {
+            // split beams move on in the same step, like in `tick`
+            let mut paths: Vec<_> = frontier
+                .drain(..)
+                .map(|i| {
+                    queued[i] = false;
+                    (beam(i), std::mem::take(&mut counts[i]))
+                })
+                .collect();
+            while let Some(((x, y, dir), n)) = paths.pop() {
+                match self.next_move(x, y, dir) {
+                    Move::To(x, y, dir) => {
+                        let i = index((x, y, dir));
+                        if !std::mem::replace(&mut queued[i], true) {
+                            next_frontier.push(i);
+                        }
+                        add(&mut counts[i], n);
+                    }
+                    Move::Split { at, sides } => {
+                        if !std::mem::replace(&mut was_hit[at.1 * width + at.0], true) {
+                            hit.push(at);
+                        }
+                        for side in sides {
+                            match side {
+                                Some((x, y)) => paths.push(((x, y, dir), n.clone())),
+                                // beams split off the edge of the floor are
+                                // lost, but they're still timelines
+                                None => add(&mut timelines, n.clone()),
+                            }
+                        }
+                    }
+                    Move::Stop => add(&mut timelines, n),
+                }
+            }
+            std::mem::swap(&mut frontier, &mut next_frontier);
+        }
+        (hit, timelines)
+    }
+
+    // both answers from a single count
+    fn parts(&self) -> (Totals<usize>, Totals<BigUint>) {
+        let counts = self.count_paths();
+        let part_a = Totals {
+            per_source: counts.per_source.iter().map(|c| c.splitters_hit).collect(),
+            combined: counts.combined.splitters_hit,
+        };
+        let part_b = Totals {
+            per_source: counts.per_source.into_iter().map(|c| c.timelines).collect(),
+            combined: counts.combined.timelines,
+        };
+        (part_a, part_b)
+    }
+
+    // the answers read off the floor and beams after `simulate`
+    fn simulated_counts(&self) -> PathCounts {
+        PathCounts {
+            splitters_hit: self
+                .floor
+                .iter()
+                .flat_map(|row| row.iter())
+                .filter(|t| matches!(t, TileType::Splitter { been_hit: true }))
+                .count(),
+            timelines: self.beams.iter().map(|b| &b.num_of_paths).sum(),
+        }
     }
 }
 
 fn main() {
-    let factory = Factory::from_file("input.txt").simulate(false);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // `--edges lost|reflect|wrap` picks what happens to beams split off the
+    // side of the floor
+    let edges = match args.iter().position(|arg| arg == "--edges") {
+        Some(i) => match args[i + 1].as_str() {
+            "lost" => EdgeMode::Lost,
+            "reflect" => EdgeMode::Reflect,
+            "wrap" => EdgeMode::Wrap,
+            other => panic!("unknown edge mode `{other}`"),
+        },
+        None => EdgeMode::default(),
+    };
+    let factory = Factory::from_file("input.txt").with_edges(edges);
+    // mirrors can trap beams, and then there's no answer to give
+    if let Some((x, y, dir)) = factory.find_loop() {
+        println!("Beams loop forever through ({x}, {y}) going {dir}");
+        return;
+    }
+    // `--simulate` also steps the beams through the factory one tile at a time,
+    // printing the map at each step. `--animate <ms>` draws it in colour
+    // instead, pausing for the given number of milliseconds between steps,
+    // and `--gif <file>` saves it as an animation
+    let flag = |flag: &str| Some(&args[args.iter().position(|arg| arg == flag)? + 1]);
+    let simulated = if let Some(ms) = flag("--animate") {
+        let mut frames = AnsiFrames {
+            out: io::stdout(),
+            delay: Duration::from_millis(ms.parse().unwrap()),
+        };
+        Some(factory.clone().simulate_with(&mut frames).unwrap())
+    } else if let Some(fname) = flag("--gif") {
+        let file = io::BufWriter::new(File::create(fname).unwrap());
+        let mut frames = GifFrames::new(file, 4, Duration::from_millis(100));
+        Some(factory.clone().simulate_with(&mut frames).unwrap())
+    } else if args.iter().any(|arg| arg == "--simulate") {
+        Some(factory.clone().simulate(true))
+    } else {
+        None
+    };
+    if let Some(simulated) = simulated {
+        let counts = simulated.simulated_counts();
+        println!(
+            "Simulated: `{}` splitters hit, `{}` timelines",
+            counts.splitters_hit, counts.timelines
+        );
+    }
+
+    let (part_a, part_b) = factory.parts();
+    println!("Part A: `{}`", part_a.combined);
+    print_per_source(&factory.sources, &part_a.per_source);
+    // `--mod <p>` gives the part b count modulo p rather than exactly
+    match args.iter().position(|arg| arg == "--mod") {
+        Some(i) => {
+            let modulus = args[i + 1].parse().unwrap();
+            let counts = factory.count_paths_mod(modulus);
+            println!("Part B: `{}` (mod {modulus})", counts.combined.timelines);
+            let per_source: Vec<_> = counts.per_source.iter().map(|c| c.timelines).collect();
+            print_per_source(&factory.sources, &per_source);
+        }
+        None => {
+            println!("Part B: `{}`", part_b.combined);
+            print_per_source(&factory.sources, &part_b.per_source);
+        }
+    }
 
-    println!("Part A: `{}`", factory.part_a());
-    println!("Part B: `{}`", factory.part_b());
+    // `--impact <n>` ranks the n splitters with the most timelines through
+    // them
+    if let Some(i) = args.iter().position(|arg| arg == "--impact") {
+        print!("{}", factory.impact_report(args[i + 1].parse().unwrap()));
+    }
+
+    // `--timelines <n>` lists the first n timelines, `--timeline <k>` shows
+    // the k-th, counting from 0, and `--sample <n>` shows n picked at random
+    if let Some(i) = args.iter().position(|arg| arg == "--timelines") {
+        let n = args[i + 1].parse().unwrap();
+        for (k, timeline) in factory.iter_timelines().take(n).enumerate() {
+            println!("Timeline {k}: {timeline}");
+        }
+    }
+    let timeline_arg = |flag: &str| {
+        let i = args.iter().position(|arg| arg == flag)?;
+        Some(args[i + 1].parse::<BigUint>().unwrap())
+    };
+    if let Some(k) = timeline_arg("--timeline") {
+        match factory.timelines().nth_timeline(&k) {
+            Some(timeline) => println!("Timeline {k}: {timeline}"),
+            None => println!("There's no timeline {k}"),
+        }
+    }
+    if let Some(n) = timeline_arg("--sample") {
+        let timelines = factory.timelines();
+        let mut rng = rand::thread_rng();
+        let mut i = BigUint::zero();
+        while i < n {
+            match timelines.sample(&mut rng) {
+                Some(timeline) => println!("Sampled {timeline}"),
+                None => break,
+            }
+            i += 1u32;
+        }
+    }
+}
+
+// breaks an answer down by source, when there's more than one
+fn print_per_source<T: fmt::Display>(sources: &[Source], per_source: &[T]) {
+    if sources.len() < 2 {
+        return;
+    }
+    for (Source { x, y, dir }, n) in sources.iter().zip(per_source) {
+        println!("    {dir} source at ({x}, {y}): `{n}`");
+    }
 }
 
 #[cfg(test)]
@@ -199,13 +984,419 @@ mod tests {
 
     #[test]
     fn test_a() {
-        let ws = Factory::from_file("test.txt").simulate(true);
-        assert_eq!(21, ws.part_a())
+        let ws = Factory::from_file("test.txt").simulate(false);
+        assert_eq!(21, ws.simulated_counts().splitters_hit)
     }
 
     #[test]
     fn test_b() {
-        let ws = Factory::from_file("test.txt").simulate(true);
-        assert_eq!(40, ws.part_b())
+        let ws = Factory::from_file("test.txt").simulate(false);
+        assert_eq!(BigUint::from(40u32), ws.simulated_counts().timelines)
+    }
+
+    // splitters in the last column, and in the first column which only a
+    // wrapped beam can reach
+    const EDGE_MANIFOLD: &str = "\
+..S.
+..^.
+....
+...^
+....
+^...
+....
+..^.
+....
+";
+
+    #[test]
+    fn test_edges() {
+        // the same manifold mirrored left to right should give the same
+        // answers for every mode
+        let mirrored: String = EDGE_MANIFOLD
+            .lines()
+            .map(|line| line.chars().rev().chain(['\n']).collect::<String>())
+            .collect();
+        for input in [EDGE_MANIFOLD, mirrored.as_str()] {
+            for (edges, splitters_hit, timelines) in [
+                (EdgeMode::Lost, 3, 4u32),
+                (EdgeMode::Reflect, 3, 5),
+                (EdgeMode::Wrap, 4, 5),
+            ] {
+                let factory = Factory::from_reader(input.as_bytes()).with_edges(edges);
+                let counts = factory.count_paths().combined;
+                assert_eq!(
+                    PathCounts {
+                        splitters_hit,
+                        timelines: BigUint::from(timelines)
+                    },
+                    counts,
+                    "{edges:?}"
+                );
+                assert_eq!(
+                    counts,
+                    factory.simulate(false).simulated_counts(),
+                    "{edges:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_land() {
+        for edges in [EdgeMode::Lost, EdgeMode::Reflect, EdgeMode::Wrap] {
+            assert_eq!(Some(1), edges.land(2, -1, 4));
+            assert_eq!(Some(3), edges.land(2, 1, 4));
+        }
+        assert_eq!(None, EdgeMode::Lost.land(0, -1, 4));
+        assert_eq!(None, EdgeMode::Lost.land(3, 1, 4));
+        assert_eq!(Some(1), EdgeMode::Reflect.land(0, -1, 4));
+        assert_eq!(Some(2), EdgeMode::Reflect.land(3, 1, 4));
+        assert_eq!(Some(3), EdgeMode::Wrap.land(0, -1, 4));
+        assert_eq!(Some(0), EdgeMode::Wrap.land(3, 1, 4));
+        // a single column floor has nowhere else to send the beam
+        assert_eq!(None, EdgeMode::Reflect.land(0, -1, 1));
+        assert_eq!(None, EdgeMode::Wrap.land(0, 1, 1));
+    }
+
+    // a downward source whose beam only hits the first splitter, and a
+    // leftward one whose beams are split up and down by the same splitters
+    pub(crate) const MULTI_SOURCE_MANIFOLD: &str = "\
+S......
+..^...<
+^......
+.......
+.......
+";
+
+    #[test]
+    fn test_multiple_sources() {
+        let factory = Factory::from_reader(MULTI_SOURCE_MANIFOLD.as_bytes());
+        assert_eq!(
+            vec![
+                Source {
+                    x: 0,
+                    y: 0,
+                    dir: Direction::Down
+                },
+                Source {
+                    x: 6,
+                    y: 1,
+                    dir: Direction::Left
+                },
+            ],
+            factory.sources
+        );
+        assert_eq!(
+            Totals {
+                per_source: vec![1, 2],
+                combined: 2
+            },
+            factory.parts().0
+        );
+        assert_eq!(
+            Totals {
+                per_source: vec![BigUint::from(2u32), BigUint::from(3u32)],
+                combined: BigUint::from(5u32)
+            },
+            factory.parts().1
+        );
+        assert_eq!(
+            factory.count_paths().combined,
+            factory.simulate(false).simulated_counts()
+        );
+    }
+
+    #[test]
+    fn test_directions() {
+        // the example turned on its side, or upside down, with the source
+        // pointing the same way gives the same answers
+        let input = std::fs::read_to_string("test.txt").unwrap();
+        let rows: Vec<Vec<char>> = input.lines().map(|l| l.chars().collect()).collect();
+        let turn = |source: char, f: &dyn Fn(usize, usize) -> char, w: usize, h: usize| {
+            let mut out = String::new();
+            for y in 0..h {
+                for x in 0..w {
+                    out.push(match f(x, y) {
+                        'S' => source,
+                        c => c,
+                    });
+                }
+                out.push('\n');
+            }
+            out
+        };
+        let (w, h) = (rows[0].len(), rows.len());
+        for turned in [
+            turn('A', &|x, y| rows[h - 1 - y][x], w, h),
+            turn('>', &|x, y| rows[x][y], h, w),
+            turn('<', &|x, y| rows[h - 1 - x][y], h, w),
+        ] {
+            let factory = Factory::from_reader(turned.as_bytes());
+            let counts = factory.count_paths().combined;
+            assert_eq!(
+                PathCounts {
+                    splitters_hit: 21,
+                    timelines: BigUint::from(40u32)
+                },
+                counts,
+                "{turned}"
+            );
+            assert_eq!(counts, factory.simulate(false).simulated_counts());
+        }
+    }
+
+    #[test]
+    fn test_tile_chars() {
+        let chars = ".^SA<>|/\\#UDLR";
+        let round_trip: String = chars
+            .chars()
+            .map(|c| char::from(TileType::from(c)))
+            .collect();
+        assert_eq!(chars, round_trip);
+        assert_eq!('S', char::from(TileType::from('v')));
+    }
+
+    // the source's beam is turned right by the mirror, then split up and
+    // down by the splitter at the end of the row. `filter` sits in the way
+    pub(crate) fn mirror_manifold(filter: char) -> Factory {
+        let input = [
+            ".S.....",
+            ".......",
+            format!(".\\..{filter}.^").as_str(),
+            ".......",
+        ]
+        .join("\n");
+        Factory::from_reader(input.as_bytes())
+    }
+
+    #[test]
+    fn test_mirrors_and_filters() {
+        for (filter, splitters_hit, timelines) in [
+            ('.', 1, 2u32),
+            ('R', 1, 2),
+            // every other filter stops the beam before the splitter
+            ('L', 0, 1),
+            ('U', 0, 1),
+            ('D', 0, 1),
+            ('#', 0, 1),
+        ] {
+            let factory = mirror_manifold(filter);
+            let counts = factory.count_paths().combined;
+            assert_eq!(
+                PathCounts {
+                    splitters_hit,
+                    timelines: BigUint::from(timelines)
+                },
+                counts,
+                "{filter}"
+            );
+            assert_eq!(counts, factory.simulate(false).simulated_counts());
+        }
+    }
+
+    #[test]
+    fn test_find_loop() {
+        assert_eq!(None, Factory::from_file("test.txt").find_loop());
+        assert_eq!(None, mirror_manifold('R').find_loop());
+
+        // a source inside a ring of mirrors goes round forever
+        let ring = ["/.>.\\", ".....", "\\.../"].join("\n");
+        let factory = Factory::from_reader(ring.as_bytes());
+        assert_eq!(Some((2, 0, Direction::Right)), factory.find_loop());
+
+        // as do beams split back and forth between two splitters
+        let ping_pong = ["S.", "^^"].join("\n");
+        assert!(
+            Factory::from_reader(ping_pong.as_bytes())
+                .find_loop()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_sweep_matches_walk() {
+        // absorbers and filters but no mirrors, with a beam split straight
+        // into an absorber on each splitter's row
+        let input = "\
+..S..v
+......
+.#^.^.
+......
+.^.D.U
+......
+...^#.
+>..R.L
+";
+        let add = |acc: &mut BigUint, n| *acc += n;
+        for edges in [EdgeMode::Lost, EdgeMode::Reflect, EdgeMode::Wrap] {
+            let factory = Factory::from_reader(input.as_bytes()).with_edges(edges);
+            assert!(!factory.can_loop());
+            for &source in &factory.sources {
+                let (mut swept, n) = factory.sweep_source_with(source, BigUint::one(), &add);
+                let (mut walked, m) = factory.walk_source_with(source, BigUint::one(), &add);
+                swept.sort_unstable();
+                walked.sort_unstable();
+                assert_eq!((swept, n), (walked, m), "{edges:?} {source:?}");
+            }
+            let counts = factory.count_paths().combined;
+            assert_eq!(
+                PathCounts {
+                    splitters_hit: 2,
+                    timelines: BigUint::from(5u32)
+                },
+                counts
+            );
+            assert_eq!(counts, factory.simulate(false).simulated_counts());
+        }
+    }
+
+    #[test]
+    fn test_can_loop() {
+        // beams only going down past lone splitters can't come back
+        let factory = Factory::from_file("test.txt");
+        assert!(!factory.can_loop());
+        assert!(!factory.with_edges(EdgeMode::Wrap).can_loop());
+        assert!(mirror_manifold('.').can_loop());
+        assert!(Factory::from_reader("S.\n^^".as_bytes()).can_loop());
+
+        // splitters on opposite edges are only next to each other when the
+        // beams wrap round, and the loop found for one edge mode isn't kept
+        // for another
+        let factory = Factory::from_reader("S..\n^.^".as_bytes());
+        assert!(!factory.can_loop());
+        assert_eq!(None, factory.find_loop());
+        let factory = factory.with_edges(EdgeMode::Wrap);
+        assert!(factory.can_loop());
+        assert!(factory.find_loop().is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "beams loop forever")]
+    fn test_loop_panics() {
+        let ring = ["/.>.\\", ".....", "\\.../"].join("\n");
+        Factory::from_reader(ring.as_bytes()).count_paths();
+    }
+
+    // a triangle of `depth` rows of splitters where every beam hits a splitter
+    // on every row, so the number of timelines doubles each row
+    pub(crate) fn triangle(depth: usize) -> Factory {
+        let width = 2 * depth + 3;
+        let center = width / 2;
+        let mut input = String::new();
+        for y in 0..2 * depth + 2 {
+            for x in 0..width {
+                let i = y / 2;
+                let c = if y == 0 && x == center {
+                    'S'
+                } else if y % 2 == 0 && y > 0 && x.abs_diff(center) < i && (x + i) % 2 != center % 2
+                {
+                    '^'
+                } else {
+                    '.'
+                };
+                input.push(c);
+            }
+            input.push('\n');
+        }
+        Factory::from_reader(input.as_bytes())
+    }
+
+    #[test]
+    fn test_exact_timelines() {
+        let factory = triangle(200);
+        let counts = factory.count_paths().combined;
+        assert_eq!(200 * 201 / 2, counts.splitters_hit);
+        assert_eq!(BigUint::one() << 200, counts.timelines);
+        assert_eq!(counts, factory.simulate(false).simulated_counts());
+    }
+
+    #[test]
+    fn test_modular_timelines() {
+        let factory = Factory::from_file("test.txt");
+        assert_eq!(40 % 7, factory.count_paths_mod(7).combined.timelines);
+        assert_eq!(40, factory.count_paths_mod(u64::MAX).combined.timelines);
+        assert_eq!(0, factory.count_paths_mod(1).combined.timelines);
+
+        let factory = triangle(200);
+        let p = 1_000_000_007;
+        let exact = factory.count_paths().combined.timelines % p;
+        let counts = factory.count_paths_mod(p).combined;
+        assert_eq!(200 * 201 / 2, counts.splitters_hit);
+        assert_eq!(BigUint::from(counts.timelines), exact);
+    }
+
+    #[test]
+    fn test_dp_matches_simulation() {
+        let factory = Factory::from_file("test.txt");
+        let counts = factory.count_paths().combined;
+        assert_eq!(
+            PathCounts {
+                splitters_hit: 21,
+                timelines: BigUint::from(40u32)
+            },
+            counts
+        );
+        assert_eq!(counts, factory.simulate(false).simulated_counts());
+    }
+
+    // builds a `width` x `height` manifold with the source in the middle of
+    // the top row and splitters scattered pseudo randomly on every other row,
+    // with a one in `sparsity` chance of a splitter in each spot. Splitters
+    // are never next to each other in a row
+    pub(crate) fn big_manifold(width: usize, height: usize, sparsity: u64) -> String {
+        let mut seed: u64 = 12345;
+        let mut input = String::new();
+        for y in 0..height {
+            for x in 0..width {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let c = if y == 0 && x == width / 2 {
+                    'S'
+                } else if y % 2 == 0
+                    && y > 0
+                    && x % 2 == (y / 2) % 2
+                    && (seed >> 33).is_multiple_of(sparsity)
+                {
+                    '^'
+                } else {
+                    '.'
+                };
+                input.push(c);
+            }
+            input.push('\n');
+        }
+        input
+    }
+
+    #[test]
+    fn test_dp_matches_simulation_big() {
+        let input = big_manifold(101, 100, 10);
+        let factory = Factory::from_reader(input.as_bytes());
+        assert_eq!(
+            factory.count_paths().combined,
+            factory.simulate(false).simulated_counts()
+        );
+    }
+
+    // run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_dp_vs_simulation() {
+        use std::time::Instant;
+
+        let input = big_manifold(2001, 2000, 1);
+        let factory = Factory::from_reader(input.as_bytes());
+
+        let start = Instant::now();
+        let dp = factory.count_paths().combined;
+        let dp_time = start.elapsed();
+
+        let start = Instant::now();
+        let simulated = factory.simulate(false).simulated_counts();
+        let sim_time = start.elapsed();
+
+        assert_eq!(dp, simulated);
+        println!("count_paths: {dp_time:?}, simulate: {sim_time:?}");
     }
 }
@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use num_bigint::{BigUint, RandBigInt};
+use num_traits::{One, Zero};
+use rand::Rng;
+
+use crate::{Direction, Factory, Run, Source};
+
+// which way a splitter sends a beam. Left is the first of
+// `Direction::perpendicular`, so for beams going sideways it's up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Choice {
+    Left,
+    Right,
+}
+
+impl Choice {
+    const BOTH: [Choice; 2] = [Choice::Left, Choice::Right];
+}
+
+impl fmt::Display for Choice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Choice::Left => write!(f, "L"),
+            Choice::Right => write!(f, "R"),
+        }
+    }
+}
+
+// a single timeline: the source it starts from (an index into
+// `Factory::sources`), the way it went at each splitter, and where its beam
+// stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timeline {
+    pub source: usize,
+    pub choices: Vec<Choice>,
+    pub end: (usize, usize),
+}
+
+impl fmt::Display for Timeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "source {}: ", self.source)?;
+        for choice in &self.choices {
+            write!(f, "{choice}")?;
+        }
+        write!(f, " ending at ({}, {})", self.end.0, self.end.1)
+    }
+}
+
+// a timeline that's been started but not finished
+enum Branch {
+    Beam((usize, usize, Direction)),
+    // its beam was split off the floor from (x, y)
+    Lost(usize, usize),
+}
+
+// walks every timeline depth first, going left before right, so they come
+// out in the order `Timelines::nth_timeline` numbers them. Only the
+// timelines that have been started are kept, so it works on manifolds with
+// far too many timelines to list
+pub struct TimelineIter<'a> {
+    factory: &'a Factory,
+    next_source: usize,
+    pending: Vec<(usize, Vec<Choice>, Branch)>,
+}
+
+impl Iterator for TimelineIter<'_> {
+    type Item = Timeline;
+
+    fn next(&mut self) -> Option<Timeline> {
+        loop {
+            let Some((source, choices, branch)) = self.pending.pop() else {
+                let &Source { x, y, dir } = self.factory.sources.get(self.next_source)?;
+                self.pending
+                    .push((self.next_source, vec![], Branch::Beam((x, y, dir))));
+                self.next_source += 1;
+                continue;
+            };
+            let beam = match branch {
+                Branch::Beam(beam) => beam,
+                Branch::Lost(x, y) => {
+                    return Some(Timeline {
+                        source,
+                        choices,
+                        end: (x, y),
+                    });
+                }
+            };
+            match self.factory.run(beam) {
+                Run::Stop(x, y) => {
+                    return Some(Timeline {
+                        source,
+                        choices,
+                        end: (x, y),
+                    });
+                }
+                Run::Split {
+                    beam: (x, y, dir),
+                    sides,
+                    ..
+                } => {
+                    // right goes on the stack first so left comes off first
+                    for (choice, side) in Choice::BOTH.into_iter().zip(sides).rev() {
+                        let mut choices = choices.clone();
+                        choices.push(choice);
+                        let branch = match side {
+                            Some((x, y)) => Branch::Beam((x, y, dir)),
+                            None => Branch::Lost(x, y),
+                        };
+                        self.pending.push((source, choices, branch));
+                    }
+                }
+            }
+        }
+    }
+}
+
+// the number of timelines from each beam that's about to be split, which is
+// enough to pick out the k-th timeline without walking the ones before it
+pub struct Timelines<'a> {
+    factory: &'a Factory,
+    counts: HashMap<(usize, usize, Direction), BigUint>,
+    // the beams in `counts` in the order they were counted, so each comes
+    // after all the beams it's split into
+    order: Vec<(usize, usize, Direction)>,
+}
+
+impl<'a> Timelines<'a> {
+    // panics if the beams loop, as there'd be infinitely many timelines
+    pub fn new(factory: &'a Factory) -> Self {
+        factory.assert_no_loops();
+        let mut counts: HashMap<_, BigUint> = HashMap::new();
+        let mut order = vec![];
+        // a beam is counted once both the beams it's split into have been,
+        // so it waits on the stack until then
+        let mut stack: Vec<_> = factory
+            .sources
+            .iter()
+            .map(|&Source { x, y, dir }| (x, y, dir))
+            .collect();
+        while let Some(&beam) = stack.last() {
+            let Run::Split {
+                beam: split, sides, ..
+            } = factory.run(beam)
+            else {
+                stack.pop();
+                continue;
+            };
+            if counts.contains_key(&split) {
+                stack.pop();
+                continue;
+            }
+            let mut total = BigUint::zero();
+            let mut waiting = false;
+            for side in sides {
+                let next = side.map(|(x, y)| factory.run((x, y, split.2)));
+                match next {
+                    Some(Run::Split { beam, .. }) => match counts.get(&beam) {
+                        Some(n) => total += n,
+                        None => {
+                            waiting = true;
+                            stack.push(beam);
+                        }
+                    },
+                    // lost or stopped, either way that's one timeline
+                    _ => total += 1u32,
+                }
+            }
+            if !waiting {
+                counts.insert(split, total);
+                order.push(split);
+                stack.pop();
+            }
+        }
+        Self {
+            factory,
+            counts,
+            order,
+        }
+    }
+
+    // every beam that gets split, each after the beams it's split into
+    pub fn split_order(&self) -> &[(usize, usize, Direction)] {
+        &self.order
+    }
+
+    // the number of timelines from a beam going `dir` from (x, y)
+    pub fn count_from(&self, beam: (usize, usize, Direction)) -> BigUint {
+        match self.factory.run(beam) {
+            Run::Split { beam, .. } => self.counts[&beam].clone(),
+            Run::Stop(..) => BigUint::one(),
+        }
+    }
+
+    pub fn len(&self) -> BigUint {
+        self.factory
+            .sources
+            .iter()
+            .map(|&Source { x, y, dir }| self.count_from((x, y, dir)))
+            .sum()
+    }
+
+    // the k-th timeline in the order `Factory::iter_timelines` gives them,
+    // found by skipping over whole sides of each splitter at a time. None if
+    // there aren't that many timelines
+    pub fn nth_timeline(&self, k: &BigUint) -> Option<Timeline> {
+        let mut k = k.clone();
+        for (source, &Source { x, y, dir }) in self.factory.sources.iter().enumerate() {
+            let n = self.count_from((x, y, dir));
+            if k >= n {
+                k -= n;
+                continue;
+            }
+            let mut choices = vec![];
+            let mut beam = (x, y, dir);
+            loop {
+                match self.factory.run(beam) {
+                    Run::Stop(x, y) => {
+                        return Some(Timeline {
+                            source,
+                            choices,
+                            end: (x, y),
+                        });
+                    }
+                    Run::Split {
+                        beam: (x, y, dir),
+                        sides: [left, right],
+                        ..
+                    } => {
+                        let on_left =
+                            left.map_or_else(BigUint::one, |(x, y)| self.count_from((x, y, dir)));
+                        let side = if k < on_left {
+                            choices.push(Choice::Left);
+                            left
+                        } else {
+                            k -= on_left;
+                            choices.push(Choice::Right);
+                            right
+                        };
+                        match side {
+                            Some((x, y)) => beam = (x, y, dir),
+                            None => {
+                                return Some(Timeline {
+                                    source,
+                                    choices,
+                                    end: (x, y),
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // picks a timeline uniformly at random, or None if there aren't any
+    pub fn sample(&self, rng: &mut impl Rng) -> Option<Timeline> {
+        let len = self.len();
+        if len.is_zero() {
+            return None;
+        }
+        self.nth_timeline(&rng.gen_biguint_below(&len))
+    }
+}
+
+impl Factory {
+    // panics if the beams loop, like `timelines`, as the first timeline
+    // might never end
+    pub fn iter_timelines(&self) -> TimelineIter<'_> {
+        self.assert_no_loops();
+        TimelineIter {
+            factory: self,
+            next_source: 0,
+            pending: vec![],
+        }
+    }
+
+    pub fn timelines(&self) -> Timelines<'_> {
+        Timelines::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::*;
+    use crate::tests::triangle;
+
+    #[test]
+    fn test_iter_timelines() {
+        let factory = Factory::from_file("test.txt");
+        let all: Vec<_> = factory.iter_timelines().collect();
+        assert_eq!(40, all.len());
+        for (i, timeline) in all.iter().enumerate() {
+            assert!(!all[..i].contains(timeline), "{timeline}");
+        }
+        // every timeline in the example makes it to the bottom
+        assert!(all.iter().all(|t| t.end.1 == 15));
+        assert!(all[0].choices.iter().all(|&c| c == Choice::Left));
+        assert!(all[39].choices.iter().all(|&c| c == Choice::Right));
+    }
+
+    #[test]
+    #[should_panic(expected = "beams loop forever")]
+    fn test_iter_timelines_loop_panics() {
+        let ring = ["/.>.\\", ".....", "\\.../"].join("\n");
+        Factory::from_reader(ring.as_bytes()).iter_timelines();
+    }
+
+    #[test]
+    fn test_nth_timeline() {
+        let factory = Factory::from_file("test.txt");
+        let timelines = factory.timelines();
+        assert_eq!(BigUint::from(40u32), timelines.len());
+        for (k, timeline) in factory.iter_timelines().enumerate() {
+            assert_eq!(Some(timeline), timelines.nth_timeline(&BigUint::from(k)));
+        }
+        assert_eq!(None, timelines.nth_timeline(&BigUint::from(40u32)));
+    }
+
+    #[test]
+    fn test_nth_timeline_huge() {
+        // 2^200 timelines, far too many to enumerate, each making a choice
+        // on every row of splitters
+        let factory = triangle(200);
+        let timelines = factory.timelines();
+        assert_eq!(BigUint::one() << 200, timelines.len());
+
+        let last = timelines.nth_timeline(&((BigUint::one() << 200) - 1u32));
+        assert_eq!(Some(vec![Choice::Right; 200]), last.map(|t| t.choices));
+
+        // the bits of k, most significant first, are the choices
+        let k = (BigUint::one() << 199) + 5u32;
+        let choices = timelines.nth_timeline(&k).unwrap().choices;
+        let mut expected = vec![Choice::Left; 200];
+        expected[0] = Choice::Right;
+        expected[197] = Choice::Right;
+        expected[199] = Choice::Right;
+        assert_eq!(expected, choices);
+    }
+
+    #[test]
+    fn test_multiple_sources() {
+        let factory = Factory::from_reader(crate::tests::MULTI_SOURCE_MANIFOLD.as_bytes());
+        let timelines = factory.timelines();
+        let all: Vec<_> = factory.iter_timelines().collect();
+        assert_eq!(BigUint::from(all.len()), timelines.len());
+        assert_eq!(
+            vec![0, 0, 1, 1, 1],
+            all.iter().map(|t| t.source).collect::<Vec<_>>()
+        );
+        // the first source's left beam is lost off the floor
+        assert_eq!((0, 1), all[0].end);
+        for (k, timeline) in all.into_iter().enumerate() {
+            assert_eq!(Some(timeline), timelines.nth_timeline(&BigUint::from(k)));
+        }
+    }
+
+    #[test]
+    fn test_sample() {
+        let factory = Factory::from_file("test.txt");
+        let all: Vec<_> = factory.iter_timelines().collect();
+        let timelines = factory.timelines();
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut seen = vec![0; all.len()];
+        for _ in 0..4000 {
+            let timeline = timelines.sample(&mut rng).unwrap();
+            seen[all.iter().position(|t| *t == timeline).unwrap()] += 1;
+        }
+        // roughly 100 each
+        assert!(seen.iter().all(|&n| (50..150).contains(&n)), "{seen:?}");
+
+        let empty = Factory::from_reader("...".as_bytes());
+        assert_eq!(None, empty.timelines().sample(&mut rng));
+    }
+}
@@ -0,0 +1,201 @@
+use std::fmt::Write;
+
+use crate::{Circuits, Connection, Factory, JunctionId};
+
+// the name of each axis of a junction's position in the exported graphs
+fn axis_names(dims: usize) -> Vec<String> {
+    match dims {
+        0..=4 => ["x", "y", "z", "w"][..dims]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+        _ => (0..dims).map(|axis| format!("x{axis}")).collect(),
+    }
+}
+
+impl Factory {
+    fn dims(&self) -> usize {
+        self.junctions.first().map_or(0, |j| j.coords.len())
+    }
+
+    // the junctions wired together by `connections`, one circuit at a time,
+    // each in order of id and the circuits in order of their smallest id
+    fn wired_circuits(&self, connections: &[Connection]) -> Vec<Vec<JunctionId>> {
+        let mut circuits = Circuits::new(self.junctions.len());
+        for conn in connections {
+            circuits.add_connection(conn);
+        }
+        // the index in `wired` of the circuit each root stands for
+        let mut index = vec![None; self.junctions.len()];
+        let mut wired: Vec<Vec<JunctionId>> = vec![];
+        for junction in &self.junctions {
+            let root = circuits.find(junction.id);
+            let i = *index[root.0].get_or_insert_with(|| {
+                wired.push(vec![]);
+                wired.len() - 1
+            });
+            wired[i].push(junction.id);
+        }
+        wired
+    }
+
+    // the junctions and `connections` as an undirected Graphviz graph. Every
+    // circuit with more than one junction in it is drawn as a cluster, and
+    // each connection's length is its `len`, which neato and fdp try to draw
+    // it at. Graphviz weights are integers that pull edges shorter, so the
+    // lengths can't go there
+    pub fn to_dot(&self, connections: &[Connection]) -> String {
+        let axes = axis_names(self.dims());
+        let mut dot = String::from("graph circuits {\n");
+        for (i, circuit) in self.wired_circuits(connections).iter().enumerate() {
+            let indent = if circuit.len() > 1 {
+                writeln!(dot, "  subgraph cluster_{i} {{").unwrap();
+                writeln!(dot, "    label=\"circuit {i}\";").unwrap();
+                "    "
+            } else {
+                "  "
+            };
+            for &id in circuit {
+                let coords: Vec<_> = axes
+                    .iter()
+                    .zip(&self.junctions[id.0].coords)
+                    .map(|(axis, c)| format!("{axis}={c}"))
+                    .collect();
+                writeln!(dot, "{indent}j{} [{}];", id.0, coords.join(", ")).unwrap();
+            }
+            if circuit.len() > 1 {
+                writeln!(dot, "  }}").unwrap();
+            }
+        }
+        for conn in connections {
+            let (l, r) = conn.junctions;
+            let length = self.metric.length(conn.distance);
+            writeln!(dot, "  j{} -- j{} [len={length}];", l.0, r.0).unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    // the same graph as `to_dot` in GraphML. Circuits with more than one
+    // junction are nested graphs, and every junction also has the circuit
+    // it's in as an attribute for tools that don't understand nesting
+    pub fn to_graphml(&self, connections: &[Connection]) -> String {
+        let axes = axis_names(self.dims());
+        let mut xml = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+        ));
+        let keys = axes.iter().map(|axis| (axis.as_str(), "long", "node"));
+        for (key, kind, domain) in
+            keys.chain([("circuit", "int", "node"), ("weight", "double", "edge")])
+        {
+            writeln!(
+                xml,
+                "  <key id=\"{key}\" for=\"{domain}\" attr.name=\"{key}\" attr.type=\"{kind}\"/>"
+            )
+            .unwrap();
+        }
+        xml.push_str("  <graph id=\"circuits\" edgedefault=\"undirected\">\n");
+        for (i, circuit) in self.wired_circuits(connections).iter().enumerate() {
+            let indent = if circuit.len() > 1 {
+                writeln!(xml, "    <node id=\"c{i}\">").unwrap();
+                writeln!(xml, "      <graph id=\"c{i}:\" edgedefault=\"undirected\">").unwrap();
+                "        "
+            } else {
+                "    "
+            };
+            for &id in circuit {
+                let junction = &self.junctions[id.0];
+                writeln!(xml, "{indent}<node id=\"j{}\">", id.0).unwrap();
+                for (key, value) in axes.iter().zip(&junction.coords) {
+                    writeln!(xml, "{indent}  <data key=\"{key}\">{value}</data>").unwrap();
+                }
+                writeln!(xml, "{indent}  <data key=\"circuit\">{i}</data>").unwrap();
+                writeln!(xml, "{indent}</node>").unwrap();
+            }
+            if circuit.len() > 1 {
+                writeln!(xml, "      </graph>").unwrap();
+                writeln!(xml, "    </node>").unwrap();
+            }
+        }
+        for conn in connections {
+            let (l, r) = conn.junctions;
+            writeln!(xml, "    <edge source=\"j{}\" target=\"j{}\">", l.0, r.0).unwrap();
+            let length = self.metric.length(conn.distance);
+            writeln!(xml, "      <data key=\"weight\">{length}</data>").unwrap();
+            writeln!(xml, "    </edge>").unwrap();
+        }
+        xml.push_str("  </graph>\n</graphml>\n");
+        xml
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_forest() -> (Factory, Vec<Connection>) {
+        let factory = Factory::from_file("test.txt");
+        let connections = factory.join_nearest(10);
+        (factory, connections)
+    }
+
+    #[test]
+    fn test_wired_circuits() {
+        let (factory, connections) = example_forest();
+        let mut sizes: Vec<_> = factory
+            .wired_circuits(&connections)
+            .iter()
+            .map(Vec::len)
+            .collect();
+        sizes.sort_unstable_by(|a, b| b.cmp(a));
+        assert_eq!(vec![5, 4, 2, 2], sizes[..4]);
+        assert_eq!(20, sizes.iter().sum::<usize>());
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let (factory, connections) = example_forest();
+        let dot = factory.to_dot(&connections);
+        assert!(dot.starts_with("graph circuits {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert_eq!(4, dot.matches("subgraph cluster_").count());
+        assert_eq!(20, dot.matches(", z=").count());
+        assert_eq!(connections.len(), dot.matches(" -- ").count());
+        // the closest pair in the example, the square root of 100427 apart
+        assert!(dot.contains("  j0 -- j19 [len=316.902"));
+        assert!(!dot.contains("weight="));
+        assert!(dot.contains("j0 [x=162, y=817, z=812];"));
+    }
+
+    #[test]
+    fn test_to_graphml() {
+        let factory = Factory::from_file("test.txt");
+        let tree = factory.spanning_tree();
+        let xml = factory.to_graphml(&tree);
+        assert!(xml.contains("<key id=\"z\" for=\"node\""));
+        assert!(xml.contains("attr.name=\"weight\" attr.type=\"double\""));
+        // the last connection in part B joins 216,146,977 and 117,168,530
+        let last = (99.0f64 * 99.0 + 22.0 * 22.0 + 447.0 * 447.0).sqrt();
+        assert!(xml.contains(&format!("<data key=\"weight\">{last}</data>")));
+        // the spanning tree wires everything into one nested circuit
+        assert_eq!(1, xml.matches("<graph id=\"c0:\"").count());
+        assert_eq!(20, xml.matches("<data key=\"circuit\">0</data>").count());
+        assert_eq!(19, xml.matches("<edge ").count());
+        assert_eq!(xml.matches("<node").count(), xml.matches("</node>").count());
+        assert_eq!(
+            xml.matches("<graph ").count(),
+            xml.matches("</graph>").count()
+        );
+        assert!(xml.ends_with("</graphml>\n"));
+    }
+
+    #[test]
+    fn test_axis_names() {
+        let factory = Factory::from_reader("-1,2,3,4\n5,6,7,8\n".as_bytes());
+        let dot = factory.to_dot(&factory.spanning_tree());
+        assert!(dot.contains("j0 [x=-1, y=2, z=3, w=4];"));
+        assert_eq!(vec!["x0", "x1", "x2", "x3", "x4"], axis_names(5));
+        assert!(axis_names(0).is_empty());
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::BinaryHeap;
+
+use crate::{Distance, Junction, JunctionId, Metric};
+
+// the most junctions kept in a leaf before it's split
+const LEAF_SIZE: usize = 8;
+
+#[derive(Debug, Clone)]
+struct Node {
+    // the bounding box of the junctions under the node
+    lo: Vec<i64>,
+    hi: Vec<i64>,
+    // the node's junctions are `order[start..end]`
+    start: usize,
+    end: usize,
+    // the indices of the two halves, unless it's a leaf
+    children: Option<(usize, usize)>,
+}
+
+// a k-d tree over the junctions' positions for finding near neighbours
+// without comparing every pair. Splits on the widest axis at the median, so
+// it stays balanced however the junctions are spread out
+#[derive(Debug, Default, Clone)]
+pub struct KdTree {
+    nodes: Vec<Node>,
+    order: Vec<JunctionId>,
+}
+
+impl KdTree {
+    pub fn new(junctions: &[Junction]) -> Self {
+        let mut tree = Self {
+            nodes: vec![],
+            order: junctions.iter().map(|j| j.id).collect(),
+        };
+        if !junctions.is_empty() {
+            tree.build(junctions, 0, junctions.len());
+        }
+        tree
+    }
+
+    // builds the node for `order[start..end]`, returning its index
+    fn build(&mut self, junctions: &[Junction], start: usize, end: usize) -> usize {
+        let dims = junctions[0].coords.len();
+        let mut lo = vec![i64::MAX; dims];
+        let mut hi = vec![i64::MIN; dims];
+        for id in &self.order[start..end] {
+            for (axis, &c) in junctions[id.0].coords.iter().enumerate() {
+                lo[axis] = lo[axis].min(c);
+                hi[axis] = hi[axis].max(c);
+            }
+        }
+        let index = self.nodes.len();
+        // the widest axis, found before the box is moved into the node
+        let axis = (0..dims).max_by_key(|&axis| hi[axis].abs_diff(lo[axis]));
+        self.nodes.push(Node {
+            lo,
+            hi,
+            start,
+            end,
+            children: None,
+        });
+        if let Some(axis) = axis.filter(|_| end - start > LEAF_SIZE) {
+            let mid = (start + end) / 2;
+            self.order[start..end]
+                .select_nth_unstable_by_key(mid - start, |id| junctions[id.0].coords[axis]);
+            let left = self.build(junctions, start, mid);
+            let right = self.build(junctions, mid, end);
+            self.nodes[index].children = Some((left, right));
+        }
+        index
+    }
+
+    // the `k` junctions nearest to `from`, not counting itself, nearest
+    // first. Junctions the same distance away come in order of their ids
+    pub fn nearest(
+        &self,
+        junctions: &[Junction],
+        metric: &Metric,
+        from: JunctionId,
+        k: usize,
+    ) -> Vec<(Distance, JunctionId)> {
+        let mut best = BinaryHeap::new();
+        if k > 0 && !self.nodes.is_empty() {
+            let search = Search::new(self, junctions, metric, from);
+            search.nearest(0, k, &mut best);
+        }
+        best.into_sorted_vec()
+    }
+
+    // for each node, the circuit every junction under it is in if they're all
+    // in the same one, so a search for other circuits can skip it. `circuit`
+    // is the circuit each junction is in
+    pub fn label(&self, circuit: &[usize]) -> Vec<Option<usize>> {
+        let mut labels = vec![None; self.nodes.len()];
+        // children always come after their parents, so going backwards
+        // labels them first
+        for (index, node) in self.nodes.iter().enumerate().rev() {
+            labels[index] = match node.children {
+                None => {
+                    let first = circuit[self.order[node.start].0];
+                    self.order[node.start..node.end]
+                        .iter()
+                        .all(|id| circuit[id.0] == first)
+                        .then_some(first)
+                }
+                Some((left, right)) => labels[left].filter(|&c| labels[right] == Some(c)),
+            };
+        }
+        labels
+    }
+
+    // the nearest junction to `from` that's in a different circuit, with
+    // ties going to the smallest id. `labels` comes from `label`
+    pub fn nearest_outside(
+        &self,
+        junctions: &[Junction],
+        metric: &Metric,
+        from: JunctionId,
+        circuit: &[usize],
+        labels: &[Option<usize>],
+    ) -> Option<(Distance, JunctionId)> {
+        let mut best = None;
+        if !self.nodes.is_empty() {
+            let search = Search::new(self, junctions, metric, from);
+            search.outside(0, circuit, labels, &mut best);
+        }
+        best
+    }
+}
+
+// a search of the tree for junctions near `from`
+struct Search<'a> {
+    tree: &'a KdTree,
+    junctions: &'a [Junction],
+    metric: &'a Metric,
+    from: JunctionId,
+}
+
+impl<'a> Search<'a> {
+    fn new(
+        tree: &'a KdTree,
+        junctions: &'a [Junction],
+        metric: &'a Metric,
+        from: JunctionId,
+    ) -> Self {
+        Self {
+            tree,
+            junctions,
+            metric,
+            from,
+        }
+    }
+
+    fn distance(&self, id: JunctionId) -> Distance {
+        self.metric
+            .distance(&self.junctions[self.from.0], &self.junctions[id.0])
+    }
+
+    // the shortest distance from `from` to anywhere in the node's box
+    fn box_distance(&self, node: &Node) -> Distance {
+        let p = &self.junctions[self.from.0].coords;
+        let gaps = p.iter().enumerate().map(|(axis, &c)| {
+            let (lo, hi) = (node.lo[axis], node.hi[axis]);
+            if c < lo {
+                lo.abs_diff(c)
+            } else if c > hi {
+                c.abs_diff(hi)
+            } else {
+                0
+            }
+        });
+        self.metric.combine(gaps)
+    }
+
+    fn nearest(&self, index: usize, k: usize, best: &mut BinaryHeap<(Distance, JunctionId)>) {
+        let node = &self.tree.nodes[index];
+        // a box exactly as far away as the worst so far could still have a
+        // junction with a smaller id in it
+        if best.len() == k && self.box_distance(node) > best.peek().unwrap().0 {
+            return;
+        }
+        match node.children {
+            None => {
+                for &id in &self.tree.order[node.start..node.end] {
+                    if id == self.from {
+                        continue;
+                    }
+                    let candidate = (self.distance(id), id);
+                    if best.len() < k {
+                        best.push(candidate);
+                    } else if candidate < *best.peek().unwrap() {
+                        best.pop();
+                        best.push(candidate);
+                    }
+                }
+            }
+            Some((left, right)) => {
+                for child in self.closer_first(left, right) {
+                    self.nearest(child, k, best);
+                }
+            }
+        }
+    }
+
+    fn outside(
+        &self,
+        index: usize,
+        circuit: &[usize],
+        labels: &[Option<usize>],
+        best: &mut Option<(Distance, JunctionId)>,
+    ) {
+        let node = &self.tree.nodes[index];
+        if labels[index] == Some(circuit[self.from.0]) {
+            return;
+        }
+        if best.is_some_and(|(d, _)| self.box_distance(node) > d) {
+            return;
+        }
+        match node.children {
+            None => {
+                for &id in &self.tree.order[node.start..node.end] {
+                    if circuit[id.0] == circuit[self.from.0] {
+                        continue;
+                    }
+                    let candidate = (self.distance(id), id);
+                    if best.is_none_or(|b| candidate < b) {
+                        *best = Some(candidate);
+                    }
+                }
+            }
+            Some((left, right)) => {
+                for child in self.closer_first(left, right) {
+                    self.outside(child, circuit, labels, best);
+                }
+            }
+        }
+    }
+
+    // searching the nearer half first finds close junctions sooner, so more
+    // of the further half can be skipped
+    fn closer_first(&self, left: usize, right: usize) -> [usize; 2] {
+        let nodes = &self.tree.nodes;
+        if self.box_distance(&nodes[left]) <= self.box_distance(&nodes[right]) {
+            [left, right]
+        } else {
+            [right, left]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{metrics, random_junctions};
+
+    fn brute_force(
+        junctions: &[Junction],
+        metric: &Metric,
+        from: JunctionId,
+    ) -> Vec<(Distance, JunctionId)> {
+        let mut all: Vec<_> = junctions
+            .iter()
+            .filter(|j| j.id != from)
+            .map(|j| (metric.distance(&junctions[from.0], j), j.id))
+            .collect();
+        all.sort();
+        all
+    }
+
+    #[test]
+    fn test_nearest() {
+        for dims in [2, 3, 4] {
+            let junctions = random_junctions(500, dims, 1000);
+            let tree = KdTree::new(&junctions);
+            for metric in &metrics(dims) {
+                for from in [0, 17, 499].map(JunctionId) {
+                    let expected = brute_force(&junctions, metric, from);
+                    for k in [1, 5, 64, 499, 1000] {
+                        assert_eq!(
+                            expected[..k.min(499)],
+                            tree.nearest(&junctions, metric, from, k)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_nearest_ties() {
+        // lots of junctions the same distance apart on a small grid
+        let junctions = random_junctions(300, 3, 4);
+        let tree = KdTree::new(&junctions);
+        for metric in &metrics(3) {
+            for from in (0..300).step_by(37).map(JunctionId) {
+                assert_eq!(
+                    brute_force(&junctions, metric, from)[..20],
+                    tree.nearest(&junctions, metric, from, 20)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_nearest_extremes() {
+        // boxes spanning the whole range of coordinates
+        let junctions: Vec<_> = [[i64::MIN, 0], [i64::MAX, 0], [0, i64::MIN], [0, i64::MAX]]
+            .into_iter()
+            .chain((0..20).map(|i| [i - 10, 10 - i]))
+            .enumerate()
+            .map(|(id, coords)| Junction {
+                coords: coords.to_vec(),
+                id: JunctionId(id),
+            })
+            .collect();
+        let tree = KdTree::new(&junctions);
+        for metric in &metrics(2) {
+            for from in [0, 1, 5, 23].map(JunctionId) {
+                assert_eq!(
+                    brute_force(&junctions, metric, from),
+                    tree.nearest(&junctions, metric, from, junctions.len())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_nearest_outside() {
+        let junctions = random_junctions(400, 3, 1000);
+        let tree = KdTree::new(&junctions);
+        // four circuits by id
+        let circuit: Vec<usize> = (0..400).map(|i| i % 4).collect();
+        let labels = tree.label(&circuit);
+        for metric in &metrics(3) {
+            for from in (0..400).step_by(13).map(JunctionId) {
+                let expected = brute_force(&junctions, metric, from)
+                    .into_iter()
+                    .find(|(_, id)| circuit[id.0] != circuit[from.0]);
+                assert_eq!(
+                    expected,
+                    tree.nearest_outside(&junctions, metric, from, &circuit, &labels)
+                );
+            }
+        }
+        // with everything in one circuit there's nothing outside it
+        let circuit = vec![0; 400];
+        let labels = tree.label(&circuit);
+        assert_eq!(
+            None,
+            tree.nearest_outside(
+                &junctions,
+                &Metric::Euclidean,
+                JunctionId(0),
+                &circuit,
+                &labels
+            )
+        );
+    }
+}
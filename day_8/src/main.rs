@@ -1,232 +1,404 @@
-use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashSet};
+mod export;
+mod kdtree;
+mod metric;
+mod wiring;
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+use kdtree::KdTree;
+use metric::{Distance, Metric};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
 struct JunctionId(usize);
 
+// a junction's position has the same number of coordinates as every other
+// junction in the factory, however many that is
 #[derive(Debug, Clone)]
 struct Junction {
-    x: u64,
-    y: u64,
-    z: u64,
+    coords: Vec<i64>,
     id: JunctionId,
 }
 
-impl Junction {
-    fn distance_sq(&self, other: &Self) -> u64 {
-        // lambda to handle underflow
-        let underflow_diff = |x1: u64, x2: u64| -> u64 {
-            let abs_diff = x1.max(x2) - x1.min(x2);
-            abs_diff * abs_diff
-        };
-
-        let mut result = underflow_diff(self.x, other.x);
-        result += underflow_diff(self.y, other.y);
-        result += underflow_diff(self.z, other.z);
-        result
-    }
-}
-
-#[derive(Debug, Clone, Eq, PartialEq)]
+// connections are ordered shortest first, with ties broken by the ids of the
+// junctions, which are kept smallest first, so whatever the metric the same
+// junctions always give the same connections in the same order
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
 struct Connection {
-    distance_sq: u64,
+    // in the factory's metric
+    distance: Distance,
     junctions: (JunctionId, JunctionId),
 }
 
-impl Ord for Connection {
-    fn cmp(&self, other: &Self) -> Ordering {
-        other.distance_sq.cmp(&self.distance_sq)
-    }
-}
-
-impl PartialOrd for Connection {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+impl Connection {
+    fn new(distance: Distance, a: JunctionId, b: JunctionId) -> Self {
+        Self {
+            distance,
+            junctions: (a.min(b), a.max(b)),
+        }
     }
 }
 
+// the circuits the junctions are wired into, as a disjoint set forest. Each
+// circuit is a tree of junctions pointing towards a root, which stands for
+// the whole circuit. Junctions start out in a circuit of their own
 #[derive(Debug, Default, Clone)]
-struct Circuit {
-    junctions: HashSet<JunctionId>,
+struct Circuits {
+    parent: Vec<JunctionId>,
+    // the size of the circuit each root stands for. Meaningless for
+    // junctions that aren't roots
+    size: Vec<usize>,
+    // how many circuits there are of each size, so the biggest can be read
+    // off the end
+    sizes: BTreeMap<usize, usize>,
+    num_circuits: usize,
 }
 
-impl PartialEq for Circuit {
-    fn eq(&self, other: &Self) -> bool {
-        self.size() == other.size()
-    }
-}
-
-impl Eq for Circuit {}
-
-impl Ord for Circuit {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.size().cmp(&other.size())
+impl Circuits {
+    fn new(num_junctions: usize) -> Self {
+        Self {
+            parent: (0..num_junctions).map(JunctionId).collect(),
+            size: vec![1; num_junctions],
+            sizes: BTreeMap::from([(1, num_junctions)]),
+            num_circuits: num_junctions,
+        }
     }
-}
 
-impl PartialOrd for Circuit {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+    // the root of the circuit `id` is in. Points every junction on the way
+    // straight at the root, so the next search is quicker
+    fn find(&mut self, id: JunctionId) -> JunctionId {
+        let mut root = id;
+        while self.parent[root.0] != root {
+            root = self.parent[root.0];
+        }
+        let mut id = id;
+        while id != root {
+            id = std::mem::replace(&mut self.parent[id.0], root);
+        }
+        root
     }
-}
 
-impl Circuit {
-    fn size(&self) -> usize {
-        self.junctions.len()
+    // adds a connection if two junctions are not already connected
+    // and returns true.
+    //
+    // If the junctions are already connected it returns false
+    fn add_connection(&mut self, conn: &Connection) -> bool {
+        if self.already_connected(conn) {
+            return false;
+        }
+        // the search in `already_connected` left both junctions pointing
+        // straight at their roots
+        let (l, r) = (self.find(conn.junctions.0), self.find(conn.junctions.1));
+        // the smaller circuit goes under the bigger one, which keeps the
+        // trees shallow
+        let (big, small) = if self.size[l.0] >= self.size[r.0] {
+            (l, r)
+        } else {
+            (r, l)
+        };
+        let (big_size, small_size) = (self.size[big.0], self.size[small.0]);
+        for size in [big_size, small_size] {
+            let count = self.sizes.get_mut(&size).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                self.sizes.remove(&size);
+            }
+        }
+        *self.sizes.entry(big_size + small_size).or_default() += 1;
+        self.parent[small.0] = big;
+        self.size[big.0] = big_size + small_size;
+        self.num_circuits -= 1;
+        true
     }
 
-    fn contains(&self, id: &JunctionId) -> bool {
-        self.junctions.contains(id)
+    fn already_connected(&mut self, conn: &Connection) -> bool {
+        self.find(conn.junctions.0) == self.find(conn.junctions.1)
     }
 
-    fn merge(&mut self, other: Self) {
-        self.junctions.extend(other.junctions.into_iter())
+    // the sizes of the `k` biggest circuits, biggest first
+    fn largest(&self, k: usize) -> Vec<usize> {
+        self.sizes
+            .iter()
+            .rev()
+            .flat_map(|(&size, &count)| std::iter::repeat_n(size, count))
+            .take(k)
+            .collect()
     }
 
-    fn add_conn(&mut self, conn: Connection) {
-        self.junctions.insert(conn.junctions.0);
-        self.junctions.insert(conn.junctions.1);
+    fn all_connected(&self) -> bool {
+        self.num_circuits <= 1
     }
 }
 
-#[derive(Debug, Default, Clone)]
-struct Circuits(BinaryHeap<Circuit>);
+// every connection between two junctions, shortest first, worked out as
+// they're needed rather than all up front.
+//
+// Each junction keeps a list of its nearest neighbours from the k-d tree, and
+// the heap holds the next unused neighbour of every junction. Running out of
+// neighbours fetches twice as many, so only as many are found as are used
+struct NearestPairs<'a> {
+    factory: &'a Factory,
+    neighbours: Vec<Vec<(Distance, JunctionId)>>,
+    // how many of each junction's neighbours have been used
+    used: Vec<usize>,
+    heap: BinaryHeap<Reverse<(Distance, JunctionId, JunctionId)>>,
+}
 
-impl Circuits {
-    // adds a connection if two junctions are not already connected
-    // and returns true.
-    //
-    // If the junctions are already connected it returns false
-    fn add_connection(&mut self, conn: Connection) -> bool {
-        if self.already_connected(&conn) {
-            return false;
+impl<'a> NearestPairs<'a> {
+    fn new(factory: &'a Factory) -> Self {
+        let mut pairs = Self {
+            factory,
+            neighbours: vec![vec![]; factory.junctions.len()],
+            used: vec![0; factory.junctions.len()],
+            heap: BinaryHeap::new(),
+        };
+        for junction in &factory.junctions {
+            pairs.push_next(junction.id);
         }
+        pairs
+    }
 
-        let mut new_circuits = Circuits::default();
-        let mut circuit_left = None;
-        let mut circuit_right = None;
-        while let Some(circuit) = self.0.pop() {
-            if circuit.contains(&conn.junctions.0) {
-                assert!(circuit_left.is_none());
-                circuit_left = Some(circuit);
-                continue;
-            }
-
-            if circuit.contains(&conn.junctions.1) {
-                assert!(circuit_right.is_none());
-                circuit_right = Some(circuit);
-                continue;
-            }
-            new_circuits.0.push(circuit);
+    fn push_next(&mut self, id: JunctionId) {
+        let (neighbours, used) = (&mut self.neighbours[id.0], self.used[id.0]);
+        if used == neighbours.len() {
+            let k = (2 * used).max(1);
+            let factory = self.factory;
+            *neighbours = factory
+                .tree
+                .nearest(&factory.junctions, &factory.metric, id, k);
+        }
+        if let Some(&(distance, other)) = neighbours.get(used) {
+            self.heap.push(Reverse((distance, id, other)));
         }
+    }
+}
 
-        match (circuit_left, circuit_right) {
-            (Some(mut l), Some(r)) => {
-                l.merge(r);
-                new_circuits.0.push(l);
-            }
-            (Some(mut c), None) => {
-                c.add_conn(conn);
-                new_circuits.0.push(c);
-            }
-            (None, Some(mut c)) => {
-                c.add_conn(conn);
-                new_circuits.0.push(c)
-            }
-            (None, None) => {
-                // neither junction exist in a circuit, we have to create one
-                let mut new_circuit = Circuit::default();
-                new_circuit.add_conn(conn);
-                new_circuits.0.push(new_circuit);
+impl Iterator for NearestPairs<'_> {
+    type Item = Connection;
+
+    fn next(&mut self) -> Option<Connection> {
+        loop {
+            let Reverse((distance, id, other)) = self.heap.pop()?;
+            self.used[id.0] += 1;
+            self.push_next(id);
+            // every pair turns up once from each end, so only the one from
+            // the smaller id counts
+            if id < other {
+                return Some(Connection::new(distance, id, other));
             }
         }
-        self.0 = new_circuits.0;
-        true
     }
+}
 
-    fn already_connected(&self, conn: &Connection) -> bool {
-        self.0.iter().any(|circuit| {
-            circuit.contains(&conn.junctions.0) && circuit.contains(&conn.junctions.1)
-        })
+// one junction a line, its coordinates separated by commas. The first line
+// decides how many coordinates there are, and every other line has to have
+// the same number. Blank lines are skipped
+fn parse_junctions(reader: impl BufRead) -> Result<Vec<Junction>, String> {
+    let mut junctions: Vec<Junction> = vec![];
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line.map_err(|err| err.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let coords = line
+            .split(',')
+            .map(|c| c.trim().parse())
+            .collect::<Result<Vec<i64>, _>>()
+            .map_err(|err| format!("line {}: {err} in {line:?}", line_no + 1))?;
+        if let Some(first) = junctions.first()
+            && first.coords.len() != coords.len()
+        {
+            return Err(format!(
+                "line {}: {} coordinates, but the first junction has {}",
+                line_no + 1,
+                coords.len(),
+                first.coords.len()
+            ));
+        }
+        let id = JunctionId(junctions.len());
+        junctions.push(Junction { coords, id });
     }
+    Ok(junctions)
 }
 
 #[derive(Debug, Default, Clone)]
 struct Factory {
-    #[allow(dead_code)]
     junctions: Vec<Junction>,
-    connections: BinaryHeap<Connection>,
-    circuits: Circuits,
+    tree: KdTree,
+    metric: Metric,
 }
 
 impl Factory {
     fn from_file(fname: impl AsRef<Path>) -> Self {
         let f = File::open(fname.as_ref()).unwrap();
-        let reader = BufReader::new(f);
-
-        let mut junctions: Vec<Junction> = vec![];
-
-        for (id, line) in reader.lines().enumerate() {
-            let id = JunctionId(id);
-            let line = line.unwrap();
-            let mut iter = line.split(',');
-            let x = iter.next().unwrap().parse().unwrap();
-            let y = iter.next().unwrap().parse().unwrap();
-            let z = iter.next().unwrap().parse().unwrap();
-            junctions.push(Junction { x, y, z, id })
-        }
-
-        let mut connections = BinaryHeap::new();
-        for (i, b1) in junctions.iter().enumerate() {
-            for b2 in &junctions[i + 1..] {
-                let distance_sq = b1.distance_sq(b2);
-                connections.push(Connection {
-                    distance_sq,
-                    junctions: (b1.id, b2.id),
-                })
-            }
-        }
+        Self::from_reader(BufReader::new(f))
+    }
+
+    // panics if the input isn't a junction a line
+    fn from_reader(reader: impl BufRead) -> Self {
+        Self::new(parse_junctions(reader).unwrap_or_else(|err| panic!("{err}")))
+    }
+
+    fn new(junctions: Vec<Junction>) -> Self {
         Self {
+            tree: KdTree::new(&junctions),
+            metric: Metric::default(),
             junctions,
-            connections,
-            circuits: Circuits::default(),
         }
     }
 
-    fn part_a(mut self, num_to_join: usize) -> usize {
-        let mut added_connections = 0;
-        while added_connections < num_to_join {
-            let conn = self.connections.pop().unwrap();
-            self.circuits.add_connection(conn);
-            added_connections += 1;
+    // measures the distances between junctions with `metric` instead of
+    // straight line distance
+    fn with_metric(mut self, metric: Metric) -> Self {
+        if let (Some(dims), Some(junction)) = (metric.dims(), self.junctions.first()) {
+            assert_eq!(
+                junction.coords.len(),
+                dims,
+                "the metric is for a different number of dimensions"
+            );
         }
-        let mut prod = 1;
-        for _ in 0..3 {
-            prod *= self.circuits.0.pop().unwrap().size();
-        }
-        prod
+        self.metric = metric;
+        self
+    }
+
+    fn nearest_pairs(&self) -> NearestPairs<'_> {
+        NearestPairs::new(self)
     }
 
-    fn part_b(mut self) -> u64 {
-        let mut conn = None;
-        while self.circuits.0.peek().map(|c| c.size()).unwrap_or_default() < self.junctions.len() {
-            conn = Some(self.connections.pop().unwrap());
-            self.circuits.add_connection(conn.clone().unwrap());
+    // the connections that join every junction up as cheaply as possible,
+    // shortest first. Connecting the nearest pairs in order until everything
+    // is connected picks exactly these, and the last of them finishes it.
+    //
+    // Found with Borůvka's algorithm: every round, each circuit is connected
+    // to the nearest junction in another circuit, which at least halves the
+    // number of circuits
+    fn spanning_tree(&self) -> Vec<Connection> {
+        let num_junctions = self.junctions.len();
+        let mut circuits = Circuits::new(num_junctions);
+        let mut tree = vec![];
+        while !circuits.all_connected() {
+            let circuit: Vec<usize> = (0..num_junctions)
+                .map(|i| circuits.find(JunctionId(i)).0)
+                .collect();
+            let labels = self.tree.label(&circuit);
+            let mut cheapest: Vec<Option<Connection>> = vec![None; num_junctions];
+            for junction in &self.junctions {
+                let Some((distance, other)) = self.tree.nearest_outside(
+                    &self.junctions,
+                    &self.metric,
+                    junction.id,
+                    &circuit,
+                    &labels,
+                ) else {
+                    continue;
+                };
+                let conn = Connection::new(distance, junction.id, other);
+                let best = &mut cheapest[circuit[junction.id.0]];
+                if best.as_ref().is_none_or(|best| conn < *best) {
+                    *best = Some(conn);
+                }
+            }
+            // connections are totally ordered, so the cheapest ones can't
+            // make a loop, but two circuits can pick the same connection
+            for conn in cheapest.into_iter().flatten() {
+                if circuits.add_connection(&conn) {
+                    tree.push(conn);
+                }
+            }
         }
+        tree.sort();
+        tree
+    }
 
-        let (l, r) = conn.unwrap().junctions;
-        self.junctions[l.0].x * self.junctions[r.0].x
+    // connects the `num_to_join` nearest pairs, returning the connections
+    // that joined two circuits, shortest first. The rest were already
+    // connected, so these are a spanning forest of the circuits
+    fn join_nearest(&self, num_to_join: usize) -> Vec<Connection> {
+        let mut circuits = Circuits::new(self.junctions.len());
+        self.nearest_pairs()
+            .take(num_to_join)
+            .filter(|conn| circuits.add_connection(conn))
+            .collect()
+    }
+
+    fn part_a(&self, num_to_join: usize) -> usize {
+        let mut wiring = self.wiring();
+        wiring.connect(num_to_join);
+        wiring.largest_sizes(3).iter().product()
+    }
+
+    // the product of the first coordinates of the last two junctions to be
+    // connected
+    fn part_b(&self) -> i128 {
+        let (l, r) = self.spanning_tree().pop().unwrap().junctions;
+        let x = |id: JunctionId| i128::from(self.junctions[id.0].coords[0]);
+        x(l) * x(r)
     }
 }
 
 fn main() {
-    let factory = Factory::from_file("input.txt");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut factory = Factory::from_file("input.txt");
+    if let Some(i) = args.iter().position(|arg| arg == "--metric") {
+        factory = factory.with_metric(args[i + 1].parse().unwrap());
+    }
 
-    println!("Part A: `{}`", factory.clone().part_a(1000));
+    println!("Part A: `{}`", factory.part_a(1000));
     println!("Part B: `{}`", factory.part_b());
+
+    // `--circuits <k>` lists the sizes of the circuits after part A's
+    // connections and the junctions in the `k` biggest
+    if let Some(i) = args.iter().position(|arg| arg == "--circuits") {
+        let mut wiring = factory.wiring();
+        wiring.connect(1000);
+        for (size, count) in wiring.histogram().iter().rev() {
+            println!("{count} circuits of {size}");
+        }
+        for circuit in wiring.largest(args[i + 1].parse().unwrap()) {
+            let ids: Vec<_> = circuit.iter().map(|id| id.0.to_string()).collect();
+            println!("{}", ids.join(","));
+        }
+    }
+    // `--connected <a>,<b>` is the step of part A that connected two
+    // junctions
+    if let Some(i) = args.iter().position(|arg| arg == "--connected") {
+        let (a, b) = args[i + 1].split_once(',').unwrap();
+        let (a, b) = (
+            JunctionId(a.parse().unwrap()),
+            JunctionId(b.parse().unwrap()),
+        );
+        let mut wiring = factory.wiring();
+        wiring.connect(1000);
+        match wiring.connected_at(a, b) {
+            Some(step) => println!("connected at step {step}"),
+            None => println!(
+                "not connected after {} steps, in circuits of {} and {}",
+                wiring.steps(),
+                wiring.circuit_of(a).len(),
+                wiring.circuit_of(b).len()
+            ),
+        }
+    }
+
+    // `--dot <file>` and `--graphml <file>` export the spanning tree from
+    // part B, or the connections part A makes with `--wiring a`
+    let dot = args.iter().position(|arg| arg == "--dot");
+    let graphml = args.iter().position(|arg| arg == "--graphml");
+    if dot.is_some() || graphml.is_some() {
+        let connections = match args.iter().position(|arg| arg == "--wiring") {
+            Some(i) if args[i + 1] == "a" => factory.join_nearest(1000),
+            _ => factory.spanning_tree(),
+        };
+        if let Some(i) = dot {
+            std::fs::write(&args[i + 1], factory.to_dot(&connections)).unwrap();
+        }
+        if let Some(i) = graphml {
+            std::fs::write(&args[i + 1], factory.to_graphml(&connections)).unwrap();
+        }
+    }
 }
 
 #[cfg(test)]
@@ -244,4 +416,246 @@ mod tests {
         let ws = Factory::from_file("test.txt");
         assert_eq!(25272, ws.part_b())
     }
+
+    #[test]
+    fn test_join_nearest() {
+        // the ten nearest pairs in the example join nine pairs of circuits,
+        // and asking again gives the same ones
+        let factory = Factory::from_file("test.txt");
+        let joined = factory.join_nearest(10);
+        assert_eq!(9, joined.len());
+        assert_eq!(joined, factory.join_nearest(10));
+        assert_eq!(19, factory.join_nearest(usize::MAX).len());
+    }
+
+    // `n` junctions scattered pseudo randomly in a `dims` dimensional cube
+    // `size` wide, centred on the origin
+    pub(crate) fn random_junctions(n: usize, dims: usize, size: i64) -> Vec<Junction> {
+        let mut seed: u64 = 12345;
+        let mut next = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as i64 % size - size / 2
+        };
+        (0..n)
+            .map(|id| Junction {
+                coords: (0..dims).map(|_| next()).collect(),
+                id: JunctionId(id),
+            })
+            .collect()
+    }
+
+    // every kind of metric for junctions with `dims` coordinates
+    pub(crate) fn metrics(dims: usize) -> Vec<Metric> {
+        vec![
+            Metric::Euclidean,
+            Metric::Manhattan,
+            Metric::Chebyshev,
+            Metric::WeightedEuclidean((0..dims as u64).map(|axis| axis % 3 + 1).collect()),
+        ]
+    }
+
+    fn all_pairs(junctions: &[Junction], metric: &Metric) -> Vec<Connection> {
+        let mut all = vec![];
+        for (i, a) in junctions.iter().enumerate() {
+            for b in &junctions[i + 1..] {
+                all.push(Connection::new(metric.distance(a, b), a.id, b.id));
+            }
+        }
+        all.sort();
+        all
+    }
+
+    #[test]
+    fn test_nearest_pairs() {
+        for junctions in [
+            Factory::from_file("test.txt").junctions,
+            random_junctions(200, 3, 1000),
+            random_junctions(200, 2, 1000),
+            random_junctions(200, 4, 1000),
+            // plenty of ties
+            random_junctions(100, 3, 5),
+        ] {
+            for metric in metrics(junctions[0].coords.len()) {
+                let expected = all_pairs(&junctions, &metric);
+                let factory = Factory::new(junctions.clone()).with_metric(metric);
+                let pairs: Vec<_> = factory.nearest_pairs().collect();
+                assert_eq!(expected, pairs);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spanning_tree() {
+        for junctions in [
+            Factory::from_file("test.txt").junctions,
+            random_junctions(300, 3, 1000),
+            random_junctions(200, 2, 1000),
+            random_junctions(200, 4, 1000),
+            random_junctions(200, 3, 5),
+        ] {
+            for metric in metrics(junctions[0].coords.len()) {
+                // connecting every pair in order until they're all connected
+                let mut circuits = Circuits::new(junctions.len());
+                let mut expected = vec![];
+                for conn in all_pairs(&junctions, &metric) {
+                    if circuits.add_connection(&conn) {
+                        expected.push(conn);
+                    }
+                }
+                let factory = Factory::new(junctions.clone()).with_metric(metric);
+                assert_eq!(expected, factory.spanning_tree());
+            }
+        }
+        assert!(Factory::new(vec![]).spanning_tree().is_empty());
+    }
+
+    #[test]
+    fn test_metric_ties() {
+        // the corners of a square are all the same Chebyshev distance apart,
+        // so the ids decide which get connected first
+        let square = "0,0\n4,0\n0,4\n4,4\n";
+        let factory = Factory::from_reader(square.as_bytes()).with_metric(Metric::Chebyshev);
+        let pairs: Vec<_> = factory
+            .nearest_pairs()
+            .map(|conn| (conn.junctions.0.0, conn.junctions.1.0))
+            .collect();
+        assert_eq!(vec![(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)], pairs);
+        let tree: Vec<_> = factory
+            .spanning_tree()
+            .into_iter()
+            .map(|conn| (conn.junctions.0.0, conn.junctions.1.0))
+            .collect();
+        assert_eq!(vec![(0, 1), (0, 2), (0, 3)], tree);
+    }
+
+    #[test]
+    fn test_far_apart() {
+        // opposite corners of a cube 3 * 2^62 across are further apart than
+        // 128 bits can square, but one step closer must still come first
+        let c: i64 = 3 << 61;
+        let far = format!("{0},{0},{0}\n{1},{1},{1}\n{1},{1},{2}\n", -c, c, c - 1);
+        let factory = Factory::from_reader(far.as_bytes());
+        let pairs: Vec<_> = factory
+            .nearest_pairs()
+            .map(|conn| (conn.junctions.0.0, conn.junctions.1.0))
+            .collect();
+        assert_eq!(vec![(1, 2), (0, 2), (0, 1)], pairs);
+        let tree: Vec<_> = factory
+            .spanning_tree()
+            .into_iter()
+            .map(|conn| (conn.junctions.0.0, conn.junctions.1.0))
+            .collect();
+        assert_eq!(vec![(1, 2), (0, 2)], tree);
+
+        // random junctions spread out to around 2^62
+        let mut junctions = random_junctions(100, 3, 1 << 31);
+        for junction in &mut junctions {
+            junction.coords.iter_mut().for_each(|c| *c <<= 32);
+        }
+        for metric in metrics(3) {
+            let expected = all_pairs(&junctions, &metric);
+            let factory = Factory::new(junctions.clone()).with_metric(metric);
+            assert_eq!(expected, factory.nearest_pairs().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_parse_junctions() {
+        let junctions = parse_junctions("-1,2\n3, -4\n\n5,6\n".as_bytes()).unwrap();
+        assert_eq!(3, junctions.len());
+        assert_eq!(vec![3, -4], junctions[1].coords);
+        assert_eq!(JunctionId(2), junctions[2].id);
+
+        let four = parse_junctions("1,2,3,4\n-5,6,7,-8".as_bytes()).unwrap();
+        assert_eq!(vec![-5, 6, 7, -8], four[1].coords);
+
+        let err = parse_junctions("1,2,3\n4,5\n".as_bytes()).unwrap_err();
+        assert_eq!("line 2: 2 coordinates, but the first junction has 3", err);
+        assert!(parse_junctions("1,2\n3,x\n".as_bytes()).is_err());
+        assert!(parse_junctions("1,2,99999999999999999999\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "line 3")]
+    fn test_inconsistent_input_panics() {
+        Factory::from_reader("1,2\n3,4\n5\n".as_bytes());
+    }
+
+    #[test]
+    fn test_negative_coordinates() {
+        // the example moved so it straddles the origin gives the same circuits
+        let shifted: Vec<_> = Factory::from_file("test.txt")
+            .junctions
+            .into_iter()
+            .map(|mut junction| {
+                junction.coords.iter_mut().for_each(|c| *c -= 500);
+                junction
+            })
+            .collect();
+        assert!(shifted.iter().any(|j| j.coords.iter().any(|&c| c < 0)));
+        assert_eq!(40, Factory::new(shifted.clone()).part_a(10));
+        // part b multiplies the shifted x coordinates
+        assert_eq!((216 - 500) * (117 - 500), Factory::new(shifted).part_b());
+    }
+
+    fn conn(l: usize, r: usize) -> Connection {
+        Connection::new(Distance::default(), JunctionId(l), JunctionId(r))
+    }
+
+    #[test]
+    fn test_circuits() {
+        let mut circuits = Circuits::new(6);
+        assert_eq!(vec![1, 1, 1], circuits.largest(3));
+
+        assert!(circuits.add_connection(&conn(0, 1)));
+        assert!(circuits.add_connection(&conn(2, 3)));
+        assert!(circuits.add_connection(&conn(1, 3)));
+        assert!(!circuits.add_connection(&conn(0, 2)));
+        assert!(circuits.already_connected(&conn(3, 0)));
+        assert!(!circuits.already_connected(&conn(0, 4)));
+        assert_eq!(vec![4, 1, 1], circuits.largest(3));
+        assert_eq!(vec![4, 1, 1], circuits.largest(10));
+        assert!(!circuits.all_connected());
+
+        assert!(circuits.add_connection(&conn(4, 5)));
+        assert_eq!(vec![4, 2], circuits.largest(3));
+        assert!(circuits.add_connection(&conn(5, 0)));
+        assert_eq!(vec![6], circuits.largest(3));
+        assert!(circuits.all_connected());
+    }
+
+    #[test]
+    fn test_find_compresses_paths() {
+        let mut circuits = Circuits::new(4);
+        // union by size keeps the first root, so 3 hangs off 0 directly
+        circuits.add_connection(&conn(0, 1));
+        circuits.add_connection(&conn(2, 3));
+        circuits.add_connection(&conn(0, 2));
+        let root = circuits.find(JunctionId(3));
+        assert_eq!(JunctionId(0), root);
+        assert!(circuits.parent.iter().all(|&p| p == root));
+    }
+
+    // run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_large_cloud() {
+        use std::time::Instant;
+
+        let start = Instant::now();
+        let factory = Factory::new(random_junctions(100_000, 3, 1_000_000));
+        let build_time = start.elapsed();
+
+        let start = Instant::now();
+        let a = factory.part_a(1000);
+        let a_time = start.elapsed();
+
+        let start = Instant::now();
+        let b = factory.part_b();
+        let b_time = start.elapsed();
+
+        println!("build: {build_time:?}, part a: {a} in {a_time:?}, part b: {b} in {b_time:?}");
+    }
 }
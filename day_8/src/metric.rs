@@ -0,0 +1,238 @@
+use std::iter::Sum;
+use std::ops::Add;
+use std::str::FromStr;
+
+use crate::Junction;
+
+// a distance worked out exactly in 256 bits. A weighted square of a
+// difference between two 64 bit coordinates takes up to 192 bits, which
+// leaves room to add up more axes than there could ever be
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Distance {
+    // the high half comes first so the derived ordering is numeric
+    hi: u128,
+    lo: u128,
+}
+
+impl Distance {
+    // `x` times `w`, splitting `x` into 64 bit halves so neither product
+    // overflows
+    fn product(x: u128, w: u64) -> Self {
+        let w = u128::from(w);
+        let high = (x >> 64) * w;
+        Distance {
+            hi: high >> 64,
+            lo: high << 64,
+        } + Distance::from(u128::from(x as u64) * w)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.hi as f64 * 2f64.powi(128) + self.lo as f64
+    }
+}
+
+impl From<u128> for Distance {
+    fn from(lo: u128) -> Self {
+        Distance { hi: 0, lo }
+    }
+}
+
+impl Add for Distance {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let (lo, carry) = self.lo.overflowing_add(other.lo);
+        Distance {
+            hi: self.hi + other.hi + u128::from(carry),
+            lo,
+        }
+    }
+}
+
+impl Sum for Distance {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Distance::default(), Add::add)
+    }
+}
+
+// how far apart two junctions are. Distances are only ever compared, so
+// Euclidean distances are left squared to keep them whole numbers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Metric {
+    #[default]
+    Euclidean,
+    Manhattan,
+    Chebyshev,
+    // Euclidean with the squared difference along each axis multiplied by
+    // its weight, also left squared. There's a weight for every axis
+    WeightedEuclidean(Vec<u64>),
+}
+
+impl Metric {
+    // the distance for the given differences along each axis. Growing any of
+    // them never makes it shorter, so the gaps to a box give the shortest
+    // distance to anything in it
+    pub fn combine(&self, diffs: impl Iterator<Item = u64>) -> Distance {
+        let square = |d: u64| u128::from(d) * u128::from(d);
+        match self {
+            Metric::Euclidean => diffs.map(|d| Distance::from(square(d))).sum(),
+            Metric::Manhattan => diffs.map(|d| Distance::from(u128::from(d))).sum(),
+            Metric::Chebyshev => Distance::from(diffs.max().map_or(0, u128::from)),
+            Metric::WeightedEuclidean(weights) => diffs
+                .zip(weights)
+                .map(|(d, &w)| Distance::product(square(d), w))
+                .sum(),
+        }
+    }
+
+    pub fn distance(&self, a: &Junction, b: &Junction) -> Distance {
+        self.combine(a.coords.iter().zip(&b.coords).map(|(a, b)| a.abs_diff(*b)))
+    }
+
+    // a distance from `distance` or `combine` as an actual length, undoing
+    // the squaring for Euclidean distances
+    pub fn length(&self, distance: Distance) -> f64 {
+        match self {
+            Metric::Euclidean | Metric::WeightedEuclidean(_) => distance.to_f64().sqrt(),
+            Metric::Manhattan | Metric::Chebyshev => distance.to_f64(),
+        }
+    }
+
+    // the number of axes the metric can measure, if it matters
+    pub fn dims(&self) -> Option<usize> {
+        match self {
+            Metric::WeightedEuclidean(weights) => Some(weights.len()),
+            _ => None,
+        }
+    }
+}
+
+// `euclidean`, `manhattan`, `chebyshev` or `weighted:` followed by a weight
+// for each axis, like `weighted:1,2,3`
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "euclidean" => Ok(Metric::Euclidean),
+            "manhattan" => Ok(Metric::Manhattan),
+            "chebyshev" => Ok(Metric::Chebyshev),
+            _ => {
+                let weights = s
+                    .strip_prefix("weighted:")
+                    .ok_or_else(|| format!("unknown metric {s:?}"))?;
+                let weights = weights
+                    .split(',')
+                    .map(|w| w.parse().map_err(|_| format!("bad weight {w:?}")))
+                    .collect::<Result<_, _>>()?;
+                Ok(Metric::WeightedEuclidean(weights))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::JunctionId;
+
+    fn junction(coords: &[i64]) -> Junction {
+        Junction {
+            coords: coords.to_vec(),
+            id: JunctionId(0),
+        }
+    }
+
+    #[test]
+    fn test_distance() {
+        let a = junction(&[1, 10, 5]);
+        let b = junction(&[4, 6, 5]);
+        assert_eq!(Distance::from(25), Metric::Euclidean.distance(&a, &b));
+        assert_eq!(Distance::from(7), Metric::Manhattan.distance(&a, &b));
+        assert_eq!(Distance::from(4), Metric::Chebyshev.distance(&b, &a));
+        assert_eq!(
+            Distance::from(9 + 3 * 16),
+            Metric::WeightedEuclidean(vec![1, 3, 100]).distance(&a, &b)
+        );
+        assert_eq!(Distance::default(), Metric::Manhattan.distance(&a, &a));
+    }
+
+    #[test]
+    fn test_distance_signed() {
+        let a = junction(&[-3, 4]);
+        let b = junction(&[3, -4]);
+        assert_eq!(Distance::from(100), Metric::Euclidean.distance(&a, &b));
+        assert_eq!(Distance::from(14), Metric::Manhattan.distance(&a, &b));
+        assert_eq!(Distance::from(8), Metric::Chebyshev.distance(&a, &b));
+    }
+
+    #[test]
+    fn test_distance_overflow() {
+        let a = junction(&[i64::MIN, i64::MIN, 0]);
+        let b = junction(&[i64::MAX, i64::MAX, 0]);
+        let far = u128::from(u64::MAX);
+        assert_eq!(Distance::from(far), Metric::Chebyshev.distance(&a, &b));
+        assert_eq!(Distance::from(2 * far), Metric::Manhattan.distance(&a, &b));
+        // two squares of nearly 2^64 don't fit in 128 bits, but are still exact
+        let c = junction(&[i64::MAX, i64::MIN, 0]);
+        let one = Metric::Euclidean.distance(&a, &c);
+        assert_eq!(Distance::from(far * far), one);
+        let two = Metric::Euclidean.distance(&a, &b);
+        assert_eq!(one + one, two);
+        // and a little closer is still shorter
+        let d = junction(&[i64::MAX, i64::MAX - 1, 0]);
+        assert!(Metric::Euclidean.distance(&a, &d) < two);
+        // weights can take a square up to 192 bits
+        let weighted = Metric::WeightedEuclidean(vec![u64::MAX, u64::MAX, 1]);
+        let expected = Distance::product(far * far, u64::MAX);
+        assert_eq!(expected + expected, weighted.distance(&a, &b));
+        assert!(weighted.distance(&a, &d) < expected + expected);
+    }
+
+    #[test]
+    fn test_product() {
+        let x = (1u128 << 100) + 12345;
+        assert_eq!(
+            Distance {
+                hi: 0,
+                lo: (x << 20) + x
+            },
+            Distance::product(x, (1 << 20) + 1)
+        );
+        // (2^128 - 1)(2^64 - 1) = 2^192 - 2^128 - 2^64 + 1
+        assert_eq!(
+            Distance {
+                hi: (1 << 64) - 2,
+                lo: u128::MAX - (1 << 64) + 2
+            },
+            Distance::product(u128::MAX, u64::MAX)
+        );
+    }
+
+    #[test]
+    fn test_length() {
+        assert_eq!(5.0, Metric::Euclidean.length(Distance::from(25)));
+        assert_eq!(
+            3.0,
+            Metric::WeightedEuclidean(vec![1, 2]).length(Distance::from(9))
+        );
+        assert_eq!(25.0, Metric::Manhattan.length(Distance::from(25)));
+        assert_eq!(25.0, Metric::Chebyshev.length(Distance::from(25)));
+        // past 128 bits
+        let big = Distance { hi: 1, lo: 0 };
+        assert_eq!(2f64.powi(64), Metric::Euclidean.length(big));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Ok(Metric::Euclidean), "euclidean".parse());
+        assert_eq!(Ok(Metric::Chebyshev), "chebyshev".parse());
+        assert_eq!(
+            Ok(Metric::WeightedEuclidean(vec![1, 2, 3])),
+            "weighted:1,2,3".parse()
+        );
+        assert_eq!(Some(2), "weighted:1,2".parse::<Metric>().unwrap().dims());
+        assert!("weighted:1,x,2".parse::<Metric>().is_err());
+        assert!("taxicab".parse::<Metric>().is_err());
+    }
+}
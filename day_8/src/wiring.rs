@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+
+use crate::{Circuits, Factory, JunctionId, NearestPairs};
+
+// the circuits as the nearest pairs are connected one at a time, for asking
+// about them after any number of connections.
+//
+// Alongside the usual `Circuits` it keeps a second forest that's never
+// compressed, where each junction remembers the step it was hung under
+// another. Steps only go up on the way to a root, so the step two junctions
+// were connected at is on the paths between them
+pub struct Wiring<'a> {
+    pairs: NearestPairs<'a>,
+    steps: usize,
+    circuits: Circuits,
+    parent: Vec<JunctionId>,
+    // the step each junction was hung under its parent at, None for roots
+    linked_at: Vec<Option<usize>>,
+    // the junctions in the circuit each root stands for, in no order
+    members: Vec<Vec<JunctionId>>,
+}
+
+impl<'a> Wiring<'a> {
+    fn new(factory: &'a Factory) -> Self {
+        let num_junctions = factory.junctions.len();
+        Self {
+            pairs: factory.nearest_pairs(),
+            steps: 0,
+            circuits: Circuits::new(num_junctions),
+            parent: (0..num_junctions).map(JunctionId).collect(),
+            linked_at: vec![None; num_junctions],
+            members: (0..num_junctions).map(|i| vec![JunctionId(i)]).collect(),
+        }
+    }
+
+    // connects the next `n` nearest pairs, or as many as there are left
+    pub fn connect(&mut self, n: usize) {
+        for _ in 0..n {
+            let Some(conn) = self.pairs.next() else {
+                return;
+            };
+            self.steps += 1;
+            if !self.circuits.add_connection(&conn) {
+                continue;
+            }
+            let (l, r) = (self.root(conn.junctions.0), self.root(conn.junctions.1));
+            let (big, small) = if self.members[l.0].len() >= self.members[r.0].len() {
+                (l, r)
+            } else {
+                (r, l)
+            };
+            self.parent[small.0] = big;
+            self.linked_at[small.0] = Some(self.steps);
+            let moved = std::mem::take(&mut self.members[small.0]);
+            self.members[big.0].extend(moved);
+        }
+    }
+
+    // the number of connections made so far, counting the ones between
+    // junctions that were already connected
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    // union by size keeps the trees shallow enough to walk without
+    // compressing them
+    fn root(&self, mut id: JunctionId) -> JunctionId {
+        while self.parent[id.0] != id {
+            id = self.parent[id.0];
+        }
+        id
+    }
+
+    // how many circuits there are of each size
+    pub fn histogram(&self) -> &BTreeMap<usize, usize> {
+        &self.circuits.sizes
+    }
+
+    // the sizes of the `k` biggest circuits, biggest first
+    pub fn largest_sizes(&self, k: usize) -> Vec<usize> {
+        self.circuits.largest(k)
+    }
+
+    // the junctions in each of the `k` biggest circuits, biggest first. Each
+    // is in order of id, and circuits the same size are in order of their
+    // smallest junction
+    pub fn largest(&self, k: usize) -> Vec<Vec<JunctionId>> {
+        let mut circuits: Vec<_> = (0..self.parent.len())
+            .map(JunctionId)
+            .filter(|&id| self.parent[id.0] == id)
+            .map(|root| {
+                let mut members = self.members[root.0].clone();
+                members.sort_unstable();
+                members
+            })
+            .collect();
+        circuits.sort_unstable_by(|a, b| b.len().cmp(&a.len()).then(a[0].cmp(&b[0])));
+        circuits.truncate(k);
+        circuits
+    }
+
+    // the junctions in the same circuit as `id`, including it, in order of id
+    pub fn circuit_of(&self, id: JunctionId) -> Vec<JunctionId> {
+        let mut members = self.members[self.root(id).0].clone();
+        members.sort_unstable();
+        members
+    }
+
+    // the step that connected `a` and `b`, or None if they aren't yet. A
+    // junction is connected to itself from the start, at step 0
+    pub fn connected_at(&self, a: JunctionId, b: JunctionId) -> Option<usize> {
+        let (mut a, mut b) = (a, b);
+        let mut step = 0;
+        // moving up from whichever was linked first never passes the junction
+        // where the paths meet, and the step they meet at is the last link
+        while a != b {
+            let lower = match (self.linked_at[a.0], self.linked_at[b.0]) {
+                (None, None) => return None,
+                (Some(sa), Some(sb)) if sa <= sb => &mut a,
+                (Some(_), None) => &mut a,
+                _ => &mut b,
+            };
+            step = step.max(self.linked_at[lower.0].unwrap());
+            *lower = self.parent[lower.0];
+        }
+        Some(step)
+    }
+}
+
+impl Factory {
+    pub fn wiring(&self) -> Wiring<'_> {
+        Wiring::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::random_junctions;
+
+    fn ids(ids: &[usize]) -> Vec<JunctionId> {
+        ids.iter().copied().map(JunctionId).collect()
+    }
+
+    #[test]
+    fn test_example() {
+        let factory = Factory::from_file("test.txt");
+        let mut wiring = factory.wiring();
+        wiring.connect(10);
+        assert_eq!(10, wiring.steps());
+        assert_eq!(
+            &BTreeMap::from([(1, 7), (2, 2), (4, 1), (5, 1)]),
+            wiring.histogram()
+        );
+        let largest = wiring.largest(3);
+        assert_eq!(
+            vec![5, 4, 2],
+            largest.iter().map(Vec::len).collect::<Vec<_>>()
+        );
+        assert_eq!(ids(&[2, 8, 13, 17, 18]), largest[0]);
+        // the closest pair are in the second biggest
+        assert_eq!(ids(&[0, 7, 14, 19]), wiring.circuit_of(JunctionId(19)));
+        assert_eq!(Some(1), wiring.connected_at(JunctionId(0), JunctionId(19)));
+        assert_eq!(ids(&[4]), wiring.circuit_of(JunctionId(4)));
+        assert_eq!(Some(0), wiring.connected_at(JunctionId(4), JunctionId(4)));
+        assert_eq!(None, wiring.connected_at(JunctionId(4), JunctionId(0)));
+
+        // connecting everything
+        wiring.connect(usize::MAX);
+        assert_eq!(190, wiring.steps());
+        assert_eq!(&BTreeMap::from([(20, 1)]), wiring.histogram());
+        assert_eq!(vec![ids(&(0..20).collect::<Vec<_>>())], wiring.largest(5));
+    }
+
+    #[test]
+    fn test_largest_ties() {
+        // two pairs the same size come in order of their smallest junction
+        let factory = Factory::from_reader("0,0\n100,0\n1,0\n101,0\n50,50\n".as_bytes());
+        let mut wiring = factory.wiring();
+        wiring.connect(2);
+        assert_eq!(vec![ids(&[0, 2]), ids(&[1, 3])], wiring.largest(2));
+        assert_eq!(3, wiring.largest(10).len());
+    }
+
+    #[test]
+    fn test_connected_at() {
+        let factory = Factory::new(random_junctions(40, 3, 1000));
+        let mut wiring = factory.wiring();
+        wiring.connect(usize::MAX);
+
+        // replaying the connections one at a time with plain circuits
+        let mut expected = vec![vec![None; 40]; 40];
+        let mut circuits = Circuits::new(40);
+        for (step, conn) in factory.nearest_pairs().enumerate() {
+            circuits.add_connection(&conn);
+            for (a, row) in expected.iter_mut().enumerate() {
+                for (b, at) in row.iter_mut().enumerate() {
+                    if at.is_none() && circuits.find(JunctionId(a)) == circuits.find(JunctionId(b))
+                    {
+                        *at = Some(if a == b { 0 } else { step + 1 });
+                    }
+                }
+            }
+        }
+        for (a, row) in expected.iter().enumerate() {
+            for (b, &at) in row.iter().enumerate() {
+                assert_eq!(
+                    at,
+                    wiring.connected_at(JunctionId(a), JunctionId(b)),
+                    "{a} {b}"
+                );
+            }
+        }
+    }
+}